@@ -1,5 +1,5 @@
 use flipper::{Client, LfType, Args, Flipper};
-use libusb::Context;
+use rusb::Context;
 
 struct Gpio<'a, T: Client> {
     device: &'a mut T,
@@ -42,6 +42,7 @@ fn main() {
 
     let mut gpio = Gpio::new(flipper);
 
+    gpio.write(0, 0);
     let result = gpio.read(0);
     println!("Result is {}", result);
 }
\ No newline at end of file
@@ -1,5 +1,5 @@
 use flipper::{Client, LfType, Args, Flipper};
-use libusb::Context;
+use rusb::Context;
 
 struct Led<'a, T: Client> {
     device: &'a mut T,
@@ -2,13 +2,19 @@ use std::mem;
 use std::ptr;
 use std::ffi::CStr;
 use std::os::raw::{c_void, c_char};
+use std::collections::VecDeque;
+use std::time::Duration;
 
 use crate::runtime::{Client, Args};
 use crate::runtime::protocol::*;
+use crate::runtime::load::{self, LoadState};
 use crate::device::Flipper;
+use crate::device::usbip::UsbIpDevice;
+use crate::device::usb::FLIPPER_USB_VENDOR_ID;
+use crate::device::virtual_device::VirtualFlipper;
 
 use std::marker::PhantomPinned;
-use libusb::Context;
+use rusb::{Context, Device, Hotplug, HotplugBuilder, Registration, UsbContext};
 use std::pin::Pin;
 
 #[repr(u32)]
@@ -24,9 +30,91 @@ pub enum LfResult {
     lf_illegal_handle = 8,
 }
 
+/// The kind of hotplug notification reported by `lf_poll_events`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u32)]
+pub enum LfEventKind {
+    /// No event is pending.
+    lf_event_none = 0,
+    /// A new device matching Flipper's vendor ID was plugged in.
+    lf_event_attached = 1,
+    /// A previously attached device was unplugged.
+    lf_event_detached = 2,
+}
+
+/// A single hotplug notification, as reported by `lf_poll_events`.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct LfEvent {
+    pub kind: LfEventKind,
+    pub index: u32,
+}
+
+/// An attach/detach notification queued for `lf_poll_events` to drain.
+///
+/// An attach event carries the device's stable `(bus, address)` id rather
+/// than a `usb_flippers` position captured when the event was queued:
+/// `device_left`'s `usb_flippers.remove(index)` shifts every later element
+/// down, so a position captured earlier can point at the wrong device by
+/// the time it's drained. Resolving the index lazily, against the list's
+/// state at drain time, is the only way to report a position that's still
+/// correct after an intervening detach. A detach event has no such problem
+/// — its index is the position the device occupied the instant it was
+/// removed, which nothing afterward can invalidate.
+enum PendingEvent {
+    Attached { id: (u8, u8) },
+    Detached { index: u32 },
+}
+
+/// A hotplug callback that keeps `usb_flippers`/`usb_flipper_ids` and the
+/// `events` queue of the `UsbDevices` it was registered for up to date as
+/// boards are plugged and unplugged.
+///
+/// The pointers erase the lifetime of the `UsbDevices` they live inside of,
+/// the same way `UsbDevices::new` erases the lifetime of its own
+/// `usb_context`: none of the pointees are ever moved out from under the
+/// handler, since `UsbDevices` is always held behind a `Pin<Box<_>>`.
+struct UsbHotplugHandler {
+    events: *mut VecDeque<PendingEvent>,
+    usb_flippers: *mut Vec<Flipper<'static>>,
+    usb_flipper_ids: *mut Vec<(u8, u8)>,
+    usb_context: *const Context,
+}
+
+unsafe impl Send for UsbHotplugHandler {}
+
+impl Hotplug<Context> for UsbHotplugHandler {
+    fn device_arrived(&mut self, device: Device<Context>) {
+        unsafe {
+            let context: &'static Context = &*self.usb_context;
+            if let Some((flipper, id)) = Flipper::attach_usb_device(context, device) {
+                (*self.usb_flippers).push(flipper);
+                (*self.usb_flipper_ids).push(id);
+                (*self.events).push_back(PendingEvent::Attached { id });
+            }
+        }
+    }
+
+    fn device_left(&mut self, device: Device<Context>) {
+        let id = (device.bus_number(), device.address());
+        unsafe {
+            let index = match (*self.usb_flipper_ids).iter().position(|&existing| existing == id) {
+                Some(index) => index,
+                None => return,
+            };
+            (*self.usb_flipper_ids).remove(index);
+            (*self.usb_flippers).remove(index);
+            (*self.events).push_back(PendingEvent::Detached { index: index as u32 });
+        }
+    }
+}
+
 struct UsbDevices<'a> {
     usb_context: Context,
     usb_flippers: Vec<Flipper<'a>>,
+    usb_flipper_ids: Vec<(u8, u8)>,
+    events: VecDeque<PendingEvent>,
+    _hotplug: Option<Registration<Context>>,
     _pin: PhantomPinned,
 }
 
@@ -35,6 +123,9 @@ impl<'a> UsbDevices<'a> {
         let devices = UsbDevices {
             usb_context,
             usb_flippers: vec![],
+            usb_flipper_ids: vec![],
+            events: VecDeque::new(),
+            _hotplug: None,
             _pin: PhantomPinned,
         };
         let mut boxed: Pin<Box<UsbDevices>> = Box::pin(devices);
@@ -42,8 +133,27 @@ impl<'a> UsbDevices<'a> {
         unsafe {
             let mut_ref: Pin<&mut UsbDevices> = Pin::as_mut(&mut *(&mut boxed as *mut _));
             let usbDevices = Pin::get_unchecked_mut(mut_ref);
-            let flippers = Flipper::attach_usb(&mut usbDevices.usb_context);
-            usbDevices.usb_flippers.extend(flippers);
+            let flippers = Flipper::attach_usb_with_ids(&mut usbDevices.usb_context);
+            for (flipper, id) in flippers {
+                usbDevices.usb_flippers.push(flipper);
+                usbDevices.usb_flipper_ids.push(id);
+            }
+
+            // Keep the returned device list live as boards are plugged and
+            // unplugged, instead of requiring callers to re-enumerate.
+            if rusb::has_hotplug() {
+                let handler = UsbHotplugHandler {
+                    events: &mut usbDevices.events as *mut _,
+                    usb_flippers: &mut usbDevices.usb_flippers as *mut Vec<Flipper> as *mut Vec<Flipper<'static>>,
+                    usb_flipper_ids: &mut usbDevices.usb_flipper_ids as *mut _,
+                    usb_context: &usbDevices.usb_context as *const _,
+                };
+                usbDevices._hotplug = HotplugBuilder::new()
+                    .vendor_id(FLIPPER_USB_VENDOR_ID)
+                    .enumerate(false)
+                    .register(&usbDevices.usb_context, Box::new(handler))
+                    .ok();
+            }
         }
 
         boxed
@@ -51,8 +161,12 @@ impl<'a> UsbDevices<'a> {
 }
 
 enum FFIContainer<'a> {
-    Flipper(&'a mut Client),
+    /// A selected device, paired with the state of its most recent
+    /// `lf_load_module` call so `lf_get_load_state` can query it later.
+    Flipper(&'a mut Client, LoadState),
     UsbDevices(Pin<Box<UsbDevices<'a>>>),
+    RemoteDevices(Vec<UsbIpDevice>),
+    VirtualDevices(Vec<VirtualFlipper>),
     ArgsList(Args),
 }
 
@@ -82,6 +196,107 @@ pub extern "C" fn lf_attach_usb(devices: *mut *mut c_void, length: *mut u32) ->
     LfResult::lf_success
 }
 
+/// Connects to a Flipper exported over USB/IP by a remote host and returns an
+/// opaque handle to a one-device list, mirroring `lf_attach_usb`.
+///
+/// This lets a Flipper attached to another machine (e.g. a CI runner or a
+/// shared hardware farm) be invoked exactly like a locally attached device,
+/// since `lf_select`/`lf_invoke` don't distinguish how the returned handle's
+/// bytes are actually transported.
+///
+/// If a connection or import handshake to `host:port` fails, `lf_no_devices_found`
+/// is returned and `devices` is left untouched.
+#[no_mangle]
+pub extern "C" fn lf_attach_remote(host: *const c_char, port: u16, devices: *mut *mut c_void, length: *mut u32) -> LfResult {
+    if host == ptr::null() { return LfResult::lf_null_pointer; }
+
+    let host_cstr = unsafe { CStr::from_ptr(host) };
+    let host_string = match host_cstr.to_str() {
+        Ok(host_string) => host_string,
+        Err(_) => return LfResult::lf_invalid_string,
+    };
+
+    let device = match UsbIpDevice::connect(host_string, port, None) {
+        Ok(device) => device,
+        Err(_) => return LfResult::lf_no_devices_found,
+    };
+
+    let ffi_container = Box::new(FFIContainer::RemoteDevices(vec![device]));
+    let ffi_pointer = Box::into_raw(ffi_container);
+
+    unsafe {
+        *devices = ffi_pointer as *mut c_void;
+        *length = 1;
+    }
+
+    LfResult::lf_success
+}
+
+/// Returns an opaque handle to a one-device list wrapping an in-process
+/// `VirtualFlipper`, mirroring `lf_attach_usb`.
+///
+/// No real hardware is involved: the device just decodes whatever `FmrPacket`
+/// is written to it. With no handlers registered, every `lf_invoke` against
+/// it fails with `lf_invocation_error`, so this exists to let host
+/// applications and C examples be exercised end-to-end (argument packing,
+/// the select/invoke/release flow) without a board attached.
+#[no_mangle]
+pub extern "C" fn lf_attach_virtual(devices: *mut *mut c_void, length: *mut u32) -> LfResult {
+    let ffi_container = Box::new(FFIContainer::VirtualDevices(vec![VirtualFlipper::new()]));
+    let ffi_pointer = Box::into_raw(ffi_container);
+
+    unsafe {
+        *devices = ffi_pointer as *mut c_void;
+        *length = 1;
+    }
+
+    LfResult::lf_success
+}
+
+/// Drains the next pending attach/detach notification for a USB device list
+/// returned by `lf_attach_usb`, so GUI/CLI consumers don't have to
+/// re-enumerate and rebuild handles every time a board is plugged in.
+///
+/// If no event is pending, `out_event->kind` is set to `lf_event_none` and
+/// `lf_success` is still returned. If `devices` was not returned by
+/// `lf_attach_usb`, `lf_illegal_handle` is returned.
+#[no_mangle]
+pub extern "C" fn lf_poll_events(devices: *mut c_void, out_event: *mut LfEvent) -> LfResult {
+    if devices == ptr::null_mut() { return LfResult::lf_null_pointer; }
+    if out_event == ptr::null_mut() { return LfResult::lf_null_pointer; }
+
+    let mut ffi_devices_container: Box<FFIContainer> = unsafe { Box::from_raw(devices as *mut _) };
+
+    let result = match *ffi_devices_container {
+        FFIContainer::UsbDevices(ref mut devices) => unsafe {
+            let usb_devices = Pin::get_unchecked_mut(devices.as_mut());
+
+            // Give libusb a chance to fire any pending hotplug callbacks
+            // before we check the queue.
+            let _ = usb_devices.usb_context.handle_events(Some(Duration::from_millis(0)));
+
+            let event = usb_devices.events.pop_front().map(|pending| match pending {
+                // Resolved now, against the current list, rather than at
+                // the position captured when the device attached.
+                PendingEvent::Attached { id } => match usb_devices.usb_flipper_ids.iter().position(|&existing| existing == id) {
+                    Some(index) => LfEvent { kind: LfEventKind::lf_event_attached, index: index as u32 },
+                    // Attached and detached again before this event was
+                    // drained; there's no longer an index that refers to it.
+                    None => LfEvent { kind: LfEventKind::lf_event_none, index: 0 },
+                },
+                PendingEvent::Detached { index } => LfEvent { kind: LfEventKind::lf_event_detached, index },
+            }).unwrap_or(LfEvent { kind: LfEventKind::lf_event_none, index: 0 });
+            *out_event = event;
+
+            LfResult::lf_success
+        }
+        _ => LfResult::lf_illegal_handle,
+    };
+
+    mem::forget(ffi_devices_container);
+    result
+}
+
 /// Retrieves a device from the device list at the given index. Index 0 is the
 /// first device.
 ///
@@ -110,7 +325,33 @@ pub extern "C" fn lf_select(devices: *mut c_void, index: u32, device: *mut *mut
             };
 
             let ffi_pointer: *mut c_void = client
-                .map(|client| FFIContainer::Flipper(client))
+                .map(|client| FFIContainer::Flipper(client, LoadState::Idle))
+                .map(|ffi_container| Box::new(ffi_container))
+                .map(|boxed| Box::into_raw(boxed) as *mut c_void)
+                .unwrap_or(ptr::null_mut());
+
+            unsafe { *device = ffi_pointer }
+        }
+        FFIContainer::RemoteDevices(ref mut devices) => {
+            let client: Option<&mut Client> = devices
+                .get_mut(index as usize)
+                .map(|device| device as &mut dyn Client);
+
+            let ffi_pointer: *mut c_void = client
+                .map(|client| FFIContainer::Flipper(client, LoadState::Idle))
+                .map(|ffi_container| Box::new(ffi_container))
+                .map(|boxed| Box::into_raw(boxed) as *mut c_void)
+                .unwrap_or(ptr::null_mut());
+
+            unsafe { *device = ffi_pointer }
+        }
+        FFIContainer::VirtualDevices(ref mut devices) => {
+            let client: Option<&mut Client> = devices
+                .get_mut(index as usize)
+                .map(|device| device as &mut dyn Client);
+
+            let ffi_pointer: *mut c_void = client
+                .map(|client| FFIContainer::Flipper(client, LoadState::Idle))
                 .map(|ffi_container| Box::new(ffi_container))
                 .map(|boxed| Box::into_raw(boxed) as *mut c_void)
                 .unwrap_or(ptr::null_mut());
@@ -175,6 +416,7 @@ pub extern "C" fn lf_append_arg(argv: *mut *mut c_void, value: LfValue, kind: Lf
                 LfType::lf_uint16 => args.append(value as u16),
                 LfType::lf_uint32 => args.append(value as u32),
                 LfType::lf_uint64 => args.append(value as u64),
+                LfType::lf_ptr => args.append(LfPointer(value as LfAddress)),
                 _ => return LfResult::lf_illegal_type,
             };
         }
@@ -186,6 +428,82 @@ pub extern "C" fn lf_append_arg(argv: *mut *mut c_void, value: LfValue, kind: Lf
     LfResult::lf_success
 }
 
+/// Copies `len` bytes starting at `data_ptr` into a newly-allocated buffer on
+/// `device`, and writes the address of that buffer to `remote_addr`.
+///
+/// The returned address can be passed straight back into `lf_append_arg` as
+/// an `lf_ptr` argument, so a function like `uart_write(buf, len)` can be
+/// invoked against data that only exists on the host. It can also be handed
+/// to `lf_pull` later to read the buffer back.
+///
+/// The memory is not freed automatically; once the device is done with it,
+/// release it with the module that allocated it, or by loading a module
+/// whose invocation frees it.
+#[no_mangle]
+pub extern "C" fn lf_push(
+    device: *mut c_void,
+    data_ptr: *const u8,
+    len: u32,
+    remote_addr: *mut u32,
+) -> LfResult {
+    if device == ptr::null_mut() { return LfResult::lf_null_pointer; }
+    if data_ptr == ptr::null() { return LfResult::lf_null_pointer; }
+    if remote_addr == ptr::null_mut() { return LfResult::lf_null_pointer; }
+
+    let mut ffi_device_container: Box<FFIContainer> = unsafe { Box::from_raw(device as *mut _) };
+    let client = match *ffi_device_container {
+        FFIContainer::Flipper(ref mut client, _) => client,
+        _ => return LfResult::lf_illegal_handle,
+    };
+
+    let data = unsafe { std::slice::from_raw_parts(data_ptr, len as usize) };
+
+    let pointer = client.malloc(len);
+    let result = match pointer {
+        Ok(pointer) => match client.push(pointer, data) {
+            Ok(()) => {
+                unsafe { *remote_addr = pointer.0 };
+                LfResult::lf_success
+            }
+            Err(_) => LfResult::lf_invocation_error,
+        },
+        Err(_) => LfResult::lf_invocation_error,
+    };
+
+    mem::forget(ffi_device_container);
+    result
+}
+
+/// Reads `len` bytes from `remote_addr` on `device` into the local buffer
+/// `out_ptr`, as allocated by a prior `lf_push` (or by a module that
+/// returned an address via `lf_invoke`).
+#[no_mangle]
+pub extern "C" fn lf_pull(
+    device: *mut c_void,
+    remote_addr: u32,
+    out_ptr: *mut u8,
+    len: u32,
+) -> LfResult {
+    if device == ptr::null_mut() { return LfResult::lf_null_pointer; }
+    if out_ptr == ptr::null_mut() { return LfResult::lf_null_pointer; }
+
+    let mut ffi_device_container: Box<FFIContainer> = unsafe { Box::from_raw(device as *mut _) };
+    let client = match *ffi_device_container {
+        FFIContainer::Flipper(ref mut client, _) => client,
+        _ => return LfResult::lf_illegal_handle,
+    };
+
+    let buffer = unsafe { std::slice::from_raw_parts_mut(out_ptr, len as usize) };
+
+    let result = match client.pull(LfPointer(remote_addr), buffer) {
+        Ok(()) => LfResult::lf_success,
+        Err(_) => LfResult::lf_invocation_error,
+    };
+
+    mem::forget(ffi_device_container);
+    result
+}
+
 /// Executes a remote function on the given Flipper device.
 ///
 /// Flipper invocations are composed of 4 things:
@@ -259,7 +577,7 @@ pub extern "C" fn lf_invoke(
     // Reconstruct the device trait object from the raw pointer given
     let mut ffi_device_container: Box<FFIContainer> = unsafe { Box::from_raw(device as *mut _) };
     let device = match *ffi_device_container {
-        FFIContainer::Flipper(ref mut client) => client,
+        FFIContainer::Flipper(ref mut client, _) => client,
         _ => return LfResult::lf_illegal_handle,
     };
 
@@ -302,6 +620,90 @@ pub extern "C" fn lf_invoke(
     LfResult::lf_success
 }
 
+/// Mirrors `runtime::load::LoadState` across the FFI boundary.
+#[repr(u32)]
+pub enum LfLoadState {
+    lf_load_idle = 0,
+    lf_load_in_progress = 1,
+    lf_load_pushed = 2,
+    lf_load_verified = 3,
+}
+
+impl From<LoadState> for LfLoadState {
+    fn from(state: LoadState) -> Self {
+        match state {
+            LoadState::Idle => LfLoadState::lf_load_idle,
+            LoadState::InProgress => LfLoadState::lf_load_in_progress,
+            LoadState::Pushed => LfLoadState::lf_load_pushed,
+            LoadState::Verified => LfLoadState::lf_load_verified,
+        }
+    }
+}
+
+/// Deploys a user module to `device`: allocates device memory, pushes
+/// `image_len` bytes from `image_ptr` into it, then registers the module as
+/// `name` with the device's dyld.
+///
+/// The load's progress can be queried at any point with
+/// `lf_get_load_state`. If the load doesn't reach `lf_load_verified`, the
+/// allocated memory is freed automatically before this function returns, so
+/// callers don't have to roll back a partial load themselves.
+#[no_mangle]
+pub extern "C" fn lf_load_module(
+    device: *mut c_void,
+    name: *const c_char,
+    image_ptr: *const u8,
+    image_len: u32,
+) -> LfResult {
+    if device == ptr::null_mut() { return LfResult::lf_null_pointer; }
+    if name == ptr::null() { return LfResult::lf_null_pointer; }
+    if image_ptr == ptr::null() { return LfResult::lf_null_pointer; }
+
+    let mut ffi_device_container: Box<FFIContainer> = unsafe { Box::from_raw(device as *mut _) };
+    let (client, state) = match *ffi_device_container {
+        FFIContainer::Flipper(ref mut client, ref mut state) => (client, state),
+        _ => return LfResult::lf_illegal_handle,
+    };
+
+    let name_cstr = unsafe { CStr::from_ptr(name) };
+    let name_string = match name_cstr.to_str() {
+        Ok(name_string) => name_string,
+        Err(_) => return LfResult::lf_invalid_string,
+    };
+
+    let image = unsafe { std::slice::from_raw_parts(image_ptr, image_len as usize) };
+
+    let result = match load::load_module(*client, name_string, image, state) {
+        Ok(_pointer) => LfResult::lf_success,
+        Err(_) => LfResult::lf_package_not_loaded,
+    };
+
+    mem::forget(ffi_device_container);
+    result
+}
+
+/// Reports the progress of the most recent `lf_load_module` call for
+/// `device`, so a host can confirm a load completed before relying on the
+/// module, without having to thread its own bookkeeping through the FFI.
+#[no_mangle]
+pub extern "C" fn lf_get_load_state(device: *mut c_void, out_state: *mut LfLoadState) -> LfResult {
+    if device == ptr::null_mut() { return LfResult::lf_null_pointer; }
+    if out_state == ptr::null_mut() { return LfResult::lf_null_pointer; }
+
+    let mut ffi_device_container: Box<FFIContainer> = unsafe { Box::from_raw(device as *mut _) };
+
+    let result = match *ffi_device_container {
+        FFIContainer::Flipper(_, state) => {
+            unsafe { *out_state = state.into() };
+            LfResult::lf_success
+        }
+        _ => LfResult::lf_illegal_handle,
+    };
+
+    mem::forget(ffi_device_container);
+    result
+}
+
 #[no_mangle]
 pub extern "C" fn lf_release(argv: *mut c_void) -> LfResult {
     if argv == ptr::null_mut() { return LfResult::lf_null_pointer; }
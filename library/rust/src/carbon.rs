@@ -1,6 +1,6 @@
 use std::io::{self as io, Read, Write};
 use std::slice::{Iter, IterMut};
-use libusb::Context;
+use rusb::Context;
 use crate::Client;
 use crate::runtime::{
     protocol::LfType,
@@ -80,7 +80,7 @@ impl<'a> Carbon<'a> {
     }
 
     pub fn attach() -> Carbons<'a> {
-        let context = Context::new().expect("should get libusb context");
+        let context = Context::new().expect("should get usb context");
         let mut carbons = Carbons { context: Box::new(context), devices: Some(vec![]) };
 
         // Erase the lifetime of the context. We never allow a Carbon device to be moved
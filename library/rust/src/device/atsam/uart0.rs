@@ -3,7 +3,19 @@
 use std::io::{self as io, Read, Write};
 use crate::error::Result;
 use crate::runtime::{Client, Args};
-use crate::runtime::protocol::LfType;
+use crate::runtime::protocol::{LfError, LfType};
+
+/// Maps an `LfError` to the `io::ErrorKind` that best describes it to a
+/// caller driving `Uart0` through `std::io::{Read, Write}`, instead of the
+/// blanket `ErrorKind::Other` that threw the distinction away.
+fn to_io_kind(err: LfError) -> io::ErrorKind {
+    match err {
+        LfError::UnknownModule | LfError::NoHandler | LfError::InvalidPointer => io::ErrorKind::NotFound,
+        LfError::CrcMismatch => io::ErrorKind::InvalidData,
+        LfError::Io(kind) => kind,
+        LfError::MallocFailed | LfError::Overflow | LfError::Device(_) => io::ErrorKind::Other,
+    }
+}
 
 pub enum UartBaud {
     FMR,
@@ -48,13 +60,13 @@ impl<'a, T: Client> Uart0<'a, T> {
 
 impl<'a, T: Client> Write for Uart0<'a, T> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        let device_buffer = self.device.malloc(buf.len() as u32).map_err(|_| io::ErrorKind::Other)?;
-        self.device.push(device_buffer, buf).map_err(|_| io::ErrorKind::Other)?;
+        let device_buffer = self.device.malloc(buf.len() as u32).map_err(to_io_kind)?;
+        self.device.push(device_buffer, buf).map_err(to_io_kind)?;
         let mut args = Args::new();
         args.append(device_buffer)
             .append(buf.len() as u32);
-        self.device.invoke("uart0", 2, LfType::lf_void, &args).map_err(|_| io::ErrorKind::Other)?;
-        self.device.free(device_buffer).map_err(|_| io::ErrorKind::Other)?;
+        self.device.invoke("uart0", 2, LfType::lf_void, &args).map_err(to_io_kind)?;
+        self.device.free(device_buffer).map_err(to_io_kind)?;
         Ok(buf.len())
     }
     fn flush(&mut self) -> io::Result<()> {
@@ -65,12 +77,95 @@ impl<'a, T: Client> Write for Uart0<'a, T> {
 impl<'a, T: Client> Read for Uart0<'a, T> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         if buf.len() == 0 { return Ok(0) }
-        let device_buffer = self.device.malloc(buf.len() as u32).map_err(|_| io::ErrorKind::Other)?;
+        let device_buffer = self.device.malloc(buf.len() as u32).map_err(to_io_kind)?;
         let mut args = Args::new();
         args.append(device_buffer)
             .append(buf.len() as u32);
-        self.device.invoke("uart0", 3, LfType::lf_void, &args).map_err(|_| io::ErrorKind::Other)?;
-        self.device.free(device_buffer).map_err(|_| io::ErrorKind::Other)?;
+        self.device.invoke("uart0", 3, LfType::lf_void, &args).map_err(to_io_kind)?;
+        self.device.pull(device_buffer, buf).map_err(to_io_kind)?;
+        self.device.free(device_buffer).map_err(to_io_kind)?;
         Ok(buf.len())
     }
+}
+
+impl<'a, T: Client> Uart0<'a, T> {
+    /// Backs the non-blocking `embedded_hal::serial` traits' `WouldBlock`:
+    /// the same `ready()` (function index 1) check `Uart0::ready` exposes,
+    /// reimplemented against `io::Error` since `ready()` reports through
+    /// `crate::error::Result` instead.
+    fn hal_ready(&mut self) -> io::Result<bool> {
+        let args = Args::new();
+        let ready: u8 = self.device.invoke("uart0", 1, LfType::lf_uint8, &args).map_err(to_io_kind)? as u8;
+        Ok(ready != 0)
+    }
+}
+
+impl<'a, T: Client> embedded_hal::serial::Read<u8> for Uart0<'a, T> {
+    type Error = io::Error;
+
+    /// Polls `ready()`, reporting `nb::Error::WouldBlock` instead of
+    /// reading until the device says there's a byte waiting.
+    fn read(&mut self) -> nb::Result<u8, io::Error> {
+        if !self.hal_ready()? { return Err(nb::Error::WouldBlock); }
+
+        let mut byte = [0u8; 1];
+        Read::read(self, &mut byte)?;
+        Ok(byte[0])
+    }
+}
+
+impl<'a, T: Client> embedded_hal::serial::Write<u8> for Uart0<'a, T> {
+    type Error = io::Error;
+
+    /// Polls `ready()`, reporting `nb::Error::WouldBlock` instead of
+    /// writing until the device is ready to accept a byte.
+    fn write(&mut self, word: u8) -> nb::Result<(), io::Error> {
+        if !self.hal_ready()? { return Err(nb::Error::WouldBlock); }
+
+        Write::write(self, &[word])?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), io::Error> {
+        Write::flush(self).map_err(nb::Error::Other)
+    }
+}
+
+impl<'a, T: Client> embedded_hal::blocking::serial::Write<u8> for Uart0<'a, T> {
+    type Error = io::Error;
+
+    /// Uses the push/pull-backed `std::io::Write::write` directly, so the
+    /// whole buffer goes over in one `malloc`/`push`/`invoke`/`free` round
+    /// trip instead of one byte at a time.
+    fn bwrite_all(&mut self, buffer: &[u8]) -> Result<(), io::Error> {
+        Write::write(self, buffer).map(|_| ())
+    }
+
+    fn bflush(&mut self) -> Result<(), io::Error> {
+        Write::flush(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::virtual_device::VirtualFlipper;
+
+    #[test]
+    fn test_hal_read_pulls_device_memory_into_byte() {
+        let mut device = VirtualFlipper::new();
+        device.load("uart0").expect("should load uart0 module");
+        device.register("uart0", 1, |_| 1); // ready() always reports true
+        device.register("uart0", 3, |_| 0); // read invoke succeeds; firmware already wrote the byte
+
+        // `Uart0::read`'s internal `malloc` is the first allocation on a
+        // fresh `VirtualFlipper`, so it lands at address 1; seed it with a
+        // byte that's distinguishable from the all-zero buffer a missing
+        // `pull` would otherwise leave behind.
+        device.seed_heap(1, vec![0xAB]);
+
+        let mut uart = Uart0::new(&mut device);
+        let byte = embedded_hal::serial::Read::read(&mut uart).expect("should read a byte");
+        assert_eq!(byte, 0xAB);
+    }
 }
\ No newline at end of file
@@ -1,6 +1,7 @@
 use std::io::{Read, Write};
 use std::pin::Pin;
 use std::marker::PhantomPinned;
+use std::collections::HashMap;
 
 use crate::{Client, LfType, Args};
 use crate::error::Result;
@@ -11,13 +12,24 @@ use crate::runtime::{
 };
 
 lazy_static! {
-    static ref ATMEGA_MODULES: Vec<&'static str> = vec![
+    /// The co-processor new `Carbon`s route built-in modules to before any
+    /// caller has registered its own routes with `route_module`.
+    static ref DEFAULT_ATMEGA_MODULES: Vec<&'static str> = vec![
         "led",
     ];
 }
 
+/// Identifies which of Carbon's two co-processors a module's `invoke`/`load`
+/// calls should be routed to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum McuTarget {
+    AtmegaU2,
+    Atsam4s,
+}
+
 pub struct Carbon<'a, Atmega: Client> {
     modules: Modules,
+    routes: HashMap<String, McuTarget>,
     atmegau2: Atmega,
     atsam4s: Option<AtsamClient<'a, Atmega>>,
     _pin: PhantomPinned,
@@ -25,8 +37,13 @@ pub struct Carbon<'a, Atmega: Client> {
 
 impl<'a, Atmega: Client> Carbon<'a, Atmega> {
     pub fn new(atmegau2: Atmega) -> Pin<Box<Carbon<'a, Atmega>>> {
+        let routes = DEFAULT_ATMEGA_MODULES.iter()
+            .map(|&module| (module.to_string(), McuTarget::AtmegaU2))
+            .collect();
+
         let carbon = Carbon {
             modules: Modules::new(),
+            routes,
             atmegau2,
             atsam4s: None,
             _pin: PhantomPinned,
@@ -63,9 +80,46 @@ impl<'a, Atmega: Client> Carbon<'a, Atmega> {
     fn atsam4s(&mut self) -> &mut Client {
         self.atsam4s.as_mut().unwrap()
     }
+
+    /// Registers `module` to be routed to `target` on future `invoke`/`load`
+    /// calls, overriding whatever it was routed to before (including the
+    /// built-in defaults). This is how a custom module deployed to either
+    /// co-processor at runtime is taught to Carbon, without having to
+    /// recompile the crate to add it to a hardcoded list.
+    pub fn route_module(&mut self, module: &str, target: McuTarget) {
+        self.routes.insert(module.to_string(), target);
+    }
+
+    /// Returns where `module` is currently routed, defaulting to the
+    /// Atsam4s for modules nobody has routed explicitly.
+    fn route(&self, module: &str) -> McuTarget {
+        self.routes.get(module).copied().unwrap_or(McuTarget::Atsam4s)
+    }
+
+    /// Public counterpart to `route`, for a caller (or a test) that wants to
+    /// confirm where `module` is routed without invoking it and inferring
+    /// the answer from which co-processor replied.
+    pub fn routed_target(&self, module: &str) -> McuTarget {
+        self.route(module)
+    }
+
+    fn target(&mut self, module: &str) -> &mut Client {
+        match self.route(module) {
+            McuTarget::AtmegaU2 => self.atmegau2(),
+            McuTarget::Atsam4s => self.atsam4s(),
+        }
+    }
 }
 
 impl<'a, T: Client> Client for Carbon<'a, T> {
+    /// Carbon's own module table, which `invoke`/`load` never consult —
+    /// they dispatch through `target`, so each co-processor's own `Client`
+    /// impl tracks what it has loaded independently. Making this routing-
+    /// aware would mean tagging `Modules` entries with an `McuTarget`, but
+    /// `Modules` is shared by every `Client` impl in the crate, not just
+    /// Carbon's, so that's a wider change than this module should make on
+    /// its own. Use `routed_target` to ask where a specific module is
+    /// routed instead.
     fn modules(&mut self) -> &mut Modules {
         &mut self.modules
     }
@@ -79,12 +133,11 @@ impl<'a, T: Client> Client for Carbon<'a, T> {
     }
 
     fn invoke(&mut self, module: &str, function: u8, ret: LfType, args: &Args) -> Result<u64> {
-        let client: &mut Client = if ATMEGA_MODULES.contains(&module) {
-            self.atmegau2()
-        } else {
-            self.atsam4s()
-        };
-        client.invoke(module, function, ret, args)
+        self.target(module).invoke(module, function, ret, args)
+    }
+
+    fn load(&mut self, module: &str) -> Result<u64> {
+        self.target(module).load(module)
     }
 }
 
@@ -1,13 +1,16 @@
-use libusb::Context;
+use rusb::{Context, Device};
 
 mod usb;
 mod atsam;
 pub mod carbon;
+pub mod usbip;
+pub mod tcp;
+pub mod virtual_device;
 
 pub use self::usb::UsbClient;
 pub use self::atsam::AtsamClient;
 pub use self::carbon::Carbon;
-use self::usb::get_usb_devices;
+use self::usb::{get_usb_devices, open_device};
 
 use std::io::{Read, Write};
 use crate::{Client, LfType, Args};
@@ -29,6 +32,31 @@ impl<'a> Flipper<'a> {
             .collect()
     }
 
+    /// Like `attach_usb`, but also returns each device's (bus, address)
+    /// identity, so a hotplug-aware caller (the capi USB device list) can
+    /// later recognize the same physical device in a `device_left`
+    /// notification.
+    pub(crate) fn attach_usb_with_ids(context: &mut Context) -> Vec<(Flipper, (u8, u8))> {
+        get_usb_devices(context).into_iter()
+            .map(|usb| {
+                let id = usb.bus_address();
+                (Flipper::new(Carbon::new(usb)), id)
+            })
+            .collect()
+    }
+
+    /// Opens a single newly-arrived USB device and wraps it as a `Flipper`,
+    /// mirroring what `attach_usb`/`attach_usb_with_ids` do for devices
+    /// found at enumeration time. Used by the capi hotplug handler to
+    /// extend a USB device list in place as boards are plugged in, instead
+    /// of requiring callers to re-enumerate.
+    pub(crate) fn attach_usb_device(context: &Context, device: Device<Context>) -> Option<(Flipper, (u8, u8))> {
+        let id = (device.bus_number(), device.address());
+        let descriptor = device.device_descriptor().ok()?;
+        let usb = open_device(context, device, descriptor)?;
+        Some((Flipper::new(Carbon::new(usb)), id))
+    }
+
     fn new<T: Client + 'a, I: Into<Box<T>>>(inner: I) -> Flipper<'a> {
         Flipper { inner: inner.into(), modules: Modules::new() }
     }
@@ -0,0 +1,153 @@
+use std::io::{self as io, Read, Write};
+use std::net::TcpStream;
+use crate::runtime::{
+    Client,
+    Modules,
+};
+
+/// A `Client` transport that speaks FMR directly over a TCP socket, for a
+/// Flipper reachable over the network (or behind a relay daemon) rather than
+/// plugged into the local machine over USB.
+///
+/// Unlike `UsbDevice`'s bulk endpoints, a TCP stream has no notion of
+/// message boundaries, so every `write` is framed with a 4-byte big-endian
+/// length prefix. But `ProtoRead`/`FmrReturn::decode` read a reply as several
+/// small `read()` calls (a `u16` here, a `u64` there) rather than one call
+/// sized to the whole reply, so `read` can't treat every call as "consume
+/// one frame" the way `write` treats every call as "produce one frame".
+/// Instead, `read` pulls an entire length-prefixed frame off the wire into
+/// an internal buffer the first time it's needed, then serves subsequent
+/// small reads out of that buffer until it's drained, at which point the
+/// next `read` fetches the next frame.
+pub struct TcpDevice {
+    stream: TcpStream,
+    modules: Modules,
+    frame: Vec<u8>,
+    frame_pos: usize,
+}
+
+impl TcpDevice {
+    /// Connects to a Flipper (or relay daemon) listening at `host:port` and
+    /// returns a `Client` that drives it exactly like a local Flipper.
+    pub fn connect(host: &str, port: u16) -> io::Result<TcpDevice> {
+        let stream = TcpStream::connect((host, port))?;
+        Ok(TcpDevice { stream, modules: Modules::new(), frame: Vec::new(), frame_pos: 0 })
+    }
+
+    /// Reads the next length-prefixed frame off the wire into `self.frame`,
+    /// resetting `self.frame_pos` so it can be drained from the start.
+    fn fill_frame(&mut self) -> io::Result<()> {
+        let mut len_prefix = [0u8; 4];
+        self.stream.read_exact(&mut len_prefix)?;
+        let len = u32::from_be_bytes(len_prefix) as usize;
+
+        self.frame.resize(len, 0);
+        self.stream.read_exact(&mut self.frame)?;
+        self.frame_pos = 0;
+        Ok(())
+    }
+}
+
+impl Read for TcpDevice {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.frame_pos >= self.frame.len() {
+            self.fill_frame()?;
+        }
+
+        let available = &self.frame[self.frame_pos..];
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.frame_pos += n;
+        Ok(n)
+    }
+}
+
+impl Write for TcpDevice {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write_all(&(buf.len() as u32).to_be_bytes())?;
+        self.stream.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl Client for TcpDevice {
+    fn modules(&mut self) -> &mut Modules {
+        &mut self.modules
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn test_length_prefixed_framing() {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("should bind");
+        let addr = listener.local_addr().expect("should get local addr");
+
+        let server = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().expect("should accept");
+            let mut len_prefix = [0u8; 4];
+            socket.read_exact(&mut len_prefix).expect("should read length prefix");
+            let len = u32::from_be_bytes(len_prefix) as usize;
+
+            let mut request = vec![0u8; len];
+            socket.read_exact(&mut request).expect("should read request");
+
+            let reply = request;
+            socket.write_all(&(reply.len() as u32).to_be_bytes()).expect("should write length prefix");
+            socket.write_all(&reply).expect("should write reply");
+        });
+
+        let mut device = TcpDevice::connect(&addr.ip().to_string(), addr.port()).expect("should connect");
+        device.write(&[1, 2, 3, 4]).expect("should write");
+
+        let mut response = [0u8; 4];
+        device.read(&mut response).expect("should read");
+        assert_eq!(response, [1, 2, 3, 4]);
+
+        server.join().expect("server thread should not panic");
+    }
+
+    #[test]
+    fn test_round_trips_fmr_return_through_multiple_small_reads() {
+        use crate::runtime::protocol::FmrReturn;
+
+        let listener = TcpListener::bind("127.0.0.1:0").expect("should bind");
+        let addr = listener.local_addr().expect("should get local addr");
+
+        let server = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().expect("should accept");
+            let mut len_prefix = [0u8; 4];
+            socket.read_exact(&mut len_prefix).expect("should read length prefix");
+            let len = u32::from_be_bytes(len_prefix) as usize;
+
+            let mut request = vec![0u8; len];
+            socket.read_exact(&mut request).expect("should read request");
+
+            let ret = FmrReturn { value: 0x1122334455667788, error: 0 };
+            let mut body = Vec::new();
+            ret.encode(&mut body).expect("should encode");
+            socket.write_all(&(body.len() as u32).to_be_bytes()).expect("should write length prefix");
+            socket.write_all(&body).expect("should write reply");
+        });
+
+        let mut device = TcpDevice::connect(&addr.ip().to_string(), addr.port()).expect("should connect");
+        device.write(&[0xAB]).expect("should write");
+
+        // `FmrReturn::decode` issues three separate small reads (u16, u64,
+        // u8) rather than one read sized to the whole reply; `read` must
+        // serve all three out of the same buffered frame.
+        let ret = FmrReturn::decode(&mut device).expect("should decode");
+        assert_eq!(ret.value, 0x1122334455667788);
+        assert_eq!(ret.error, 0);
+
+        server.join().expect("server thread should not panic");
+    }
+}
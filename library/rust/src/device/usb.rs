@@ -5,20 +5,21 @@ use crate::runtime::{
     Modules,
 };
 
-use libusb::{
+use rusb::{
     self,
-    Context, Device, DeviceDescriptor, DeviceHandle
+    UsbContext, Context, Device, DeviceDescriptor, DeviceHandle
 };
 
-const FLIPPER_USB_VENDOR_ID: u16 = 0x16C0;
+pub(crate) const FLIPPER_USB_VENDOR_ID: u16 = 0x16C0;
 
 pub struct UsbDevice<'a> {
-    device: Device<'a>,
-    handle: DeviceHandle<'a>,
+    device: Device<Context>,
+    handle: DeviceHandle<Context>,
     descriptor: DeviceDescriptor,
     read_endpoint: Endpoint,
     write_endpoint: Endpoint,
     modules: Modules,
+    _context: &'a Context,
 }
 
 impl<'a> Read for UsbDevice<'a> {
@@ -45,60 +46,78 @@ impl<'a> Client for UsbDevice<'a> {
     }
 }
 
-pub fn get_usb_devices(context: &mut Context) -> Vec<UsbDevice> {
+impl<'a> UsbDevice<'a> {
+    /// The (bus, address) pair rusb assigns this device, stable for as long
+    /// as it stays plugged in. Used to recognize a device across hotplug
+    /// arrive/leave notifications, since those only hand back a bare
+    /// `rusb::Device`, not the `UsbDevice`/`Flipper` it was wrapped as.
+    pub(crate) fn bus_address(&self) -> (u8, u8) {
+        (self.device.bus_number(), self.device.address())
+    }
+}
+
+pub fn get_usb_devices(context: &Context) -> Vec<UsbDevice> {
     let devices = context.devices().expect("should get usb devices");
 
     let mut usb_devices = vec![];
 
     // Find all usb devices with Flipper's vendor ID.
-    for mut device in devices.iter() {
-        let mut descriptor = match device.device_descriptor() {
+    for device in devices.iter() {
+        let descriptor = match device.device_descriptor() {
             Ok(descriptor) => descriptor,
             Err(_) => continue,
         };
 
         if descriptor.vendor_id() != FLIPPER_USB_VENDOR_ID { continue }
 
-        let handle = match device.open() {
-            Ok(handle) => handle,
-            Err(_) => continue,
-        };
-
-        let read_endpoint = match find_endpoint(
-            &mut device,
-            &mut descriptor,
-            libusb::TransferType::Bulk,
-            libusb::Direction::In
-        ) {
-            Some(endpoint) => endpoint,
-            _ => continue,
-        };
-
-        let write_endpoint = match find_endpoint(
-            &mut device,
-            &mut descriptor,
-            libusb::TransferType::Bulk,
-            libusb::Direction::Out
-        ) {
-            Some(endpoint) => endpoint,
-            _ => continue,
-        };
-
-        usb_devices.push(UsbDevice {
-            device,
-            descriptor,
-            handle,
-            read_endpoint,
-            write_endpoint,
-            modules: Modules::new(),
-        })
+        if let Some(device) = open_device(context, device, descriptor) {
+            usb_devices.push(device);
+        }
     }
 
     usb_devices
 }
 
-#[derive(Debug)]
-struct Endpoint {
+/// Opens a single enumerated device, finds its bulk in/out endpoints, claims
+/// the interface they live on, and wraps the result as a `UsbDevice`.
+///
+/// Shared between one-shot enumeration (`get_usb_devices`) and hotplug
+/// arrival notifications, since both need to turn a bare `Device` into a
+/// ready-to-use `Client`.
+pub(crate) fn open_device(context: &Context, mut device: Device<Context>, descriptor: DeviceDescriptor) -> Option<UsbDevice> {
+    let mut handle = device.open().ok()?;
+
+    let read_endpoint = find_endpoint(
+        &mut device,
+        &descriptor,
+        rusb::TransferType::Bulk,
+        rusb::Direction::In,
+    )?;
+
+    let write_endpoint = find_endpoint(
+        &mut device,
+        &descriptor,
+        rusb::TransferType::Bulk,
+        rusb::Direction::Out,
+    )?;
+
+    // Claim the interface and select the alternate setting the endpoints
+    // live on before any read_bulk/write_bulk call is attempted.
+    configure_endpoint(&mut handle, &write_endpoint).ok()?;
+
+    Some(UsbDevice {
+        device,
+        descriptor,
+        handle,
+        read_endpoint,
+        write_endpoint,
+        modules: Modules::new(),
+        _context: context,
+    })
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Endpoint {
     config: u8,
     iface: u8,
     setting: u8,
@@ -106,10 +125,10 @@ struct Endpoint {
 }
 
 fn find_endpoint(
-    device: &mut Device,
+    device: &mut Device<Context>,
     descriptor: &DeviceDescriptor,
-    transfer_type: libusb::TransferType,
-    direction: libusb::Direction
+    transfer_type: rusb::TransferType,
+    direction: rusb::Direction
 ) -> Option<Endpoint> {
 
     for n in 0..descriptor.num_configurations() {
@@ -138,7 +157,7 @@ fn find_endpoint(
     None
 }
 
-fn configure_endpoint(handle: &mut DeviceHandle, endpoint: &Endpoint) -> libusb::Result<()> {
+fn configure_endpoint(handle: &mut DeviceHandle<Context>, endpoint: &Endpoint) -> rusb::Result<()> {
     handle.set_active_configuration(endpoint.config)?;
     handle.claim_interface(endpoint.iface)?;
     handle.set_alternate_setting(endpoint.iface, endpoint.setting)?;
@@ -151,8 +170,8 @@ mod tests {
 
     #[test]
     fn test_list_devices() {
-        let mut context = Context::new().expect("should get libusb context");
-        let devices = get_usb_devices(&mut context);
-        println!("HEllo");
+        let context = Context::new().expect("should get usb context");
+        let devices = get_usb_devices(&context);
+        println!("found {} Flipper(s)", devices.len());
     }
 }
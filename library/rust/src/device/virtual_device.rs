@@ -0,0 +1,212 @@
+use std::io::{self as io, Read, Write};
+use std::collections::{HashMap, VecDeque};
+
+use crate::runtime::{Client, Modules};
+use crate::runtime::protocol::{FmrBody, FmrPacket, FmrReturn, LfArg, LfAddress, LfType, LfValue};
+use crate::runtime::protocol::{FMR_ERROR_INVALID_POINTER, FMR_ERROR_NO_HANDLER};
+
+/// A handler for a single module function, given the decoded call arguments
+/// and returning the value a real device would have produced.
+pub type Handler = Box<dyn Fn(&[LfArg]) -> LfValue>;
+
+/// Tracks a `push` header that has been decoded but whose payload hasn't
+/// arrived yet, since the `Client::push` default implementation writes the
+/// header and the data as two separate `write` calls.
+enum Pending {
+    PushData { address: LfAddress, len: usize },
+}
+
+/// An in-process stand-in for a Flipper device.
+///
+/// `VirtualFlipper` implements `Client` like any real transport, but instead
+/// of serializing `FmrPacket`s over USB it decodes them directly: `call`
+/// packets are dispatched to a table of handlers registered with
+/// `register`, and `push`/`pull`/`malloc`/`free` are serviced against a
+/// `HashMap`-backed heap. This lets a whole invocation, buffer transfers
+/// included, be exercised in a unit test without a board attached.
+pub struct VirtualFlipper {
+    modules: Modules,
+    handlers: HashMap<(String, u8), Handler>,
+    module_names: HashMap<u32, String>,
+    next_module: u32,
+    heap: HashMap<LfAddress, Vec<u8>>,
+    next_address: LfAddress,
+    response: VecDeque<u8>,
+    pending: Option<Pending>,
+}
+
+impl VirtualFlipper {
+    pub fn new() -> VirtualFlipper {
+        VirtualFlipper {
+            modules: Modules::new(),
+            handlers: HashMap::new(),
+            module_names: HashMap::new(),
+            next_module: 0,
+            heap: HashMap::new(),
+            next_address: 1,
+            response: VecDeque::new(),
+            pending: None,
+        }
+    }
+
+    /// Registers a handler for `module`'s function at `index`, so a `call`
+    /// packet addressed to it is serviced in-process instead of reaching
+    /// real hardware.
+    pub fn register<F>(&mut self, module: &str, index: u8, handler: F) -> &mut Self
+    where
+        F: Fn(&[LfArg]) -> LfValue + 'static,
+    {
+        self.handlers.insert((module.to_string(), index), Box::new(handler));
+        self
+    }
+
+    /// Writes `data` directly into device memory at `address`, bypassing
+    /// `push`. `Handler` can't reach the heap itself (it only sees call
+    /// arguments and returns a value), so this is how a test simulates a
+    /// module whose real firmware would have already written to a buffer
+    /// before a host `pull`s it.
+    pub(crate) fn seed_heap(&mut self, address: LfAddress, data: Vec<u8>) {
+        self.heap.insert(address, data);
+    }
+
+    fn enqueue_return(&mut self, ret: FmrReturn) {
+        let mut bytes = Vec::new();
+        ret.encode(&mut bytes).expect("FmrReturn should always encode");
+        self.response.extend(bytes);
+    }
+
+    fn dispatch(&mut self, packet: &FmrPacket) -> FmrReturn {
+        match &packet.body {
+            FmrBody::Dyld(dyld) => self.dispatch_dyld(&dyld.module),
+            FmrBody::Call(call) => self.dispatch_call(call.module, call.function, &call.args),
+            FmrBody::Malloc(memory) => self.dispatch_malloc(memory.size),
+            FmrBody::Free(memory) => self.dispatch_free(memory.ptr as LfAddress),
+            FmrBody::Pull(data) => self.dispatch_pull(data.len as usize, data.ptr as LfAddress),
+            // Handled by `write` before reaching `dispatch`, since a push
+            // needs its payload before it can be serviced.
+            FmrBody::Push(_) => FmrReturn { value: 0, error: 1 },
+        }
+    }
+
+    fn dispatch_dyld(&mut self, name: &str) -> FmrReturn {
+        let index = self.next_module;
+        self.next_module += 1;
+        self.module_names.insert(index, name.to_string());
+
+        FmrReturn { value: index as LfValue, error: 0 }
+    }
+
+    fn dispatch_call(&mut self, module: u8, function: u8, args: &[LfArg]) -> FmrReturn {
+        let name = match self.module_names.get(&(module as u32)) {
+            Some(name) => name.clone(),
+            None => return FmrReturn { value: 0, error: 1 },
+        };
+
+        match self.handlers.get(&(name, function)) {
+            Some(handler) => FmrReturn { value: handler(args), error: 0 },
+            None => FmrReturn { value: 0, error: FMR_ERROR_NO_HANDLER },
+        }
+    }
+
+    fn dispatch_malloc(&mut self, size: u32) -> FmrReturn {
+        let address = self.next_address;
+        self.next_address += size.max(1);
+        // Keeps whatever `seed_heap` already placed at this address.
+        self.heap.entry(address).or_insert_with(|| vec![0u8; size as usize]);
+        FmrReturn { value: address as LfValue, error: 0 }
+    }
+
+    fn dispatch_free(&mut self, address: LfAddress) -> FmrReturn {
+        match self.heap.remove(&address) {
+            Some(_) => FmrReturn { value: 0, error: 0 },
+            None => FmrReturn { value: 0, error: FMR_ERROR_INVALID_POINTER },
+        }
+    }
+
+    fn dispatch_pull(&mut self, len: usize, address: LfAddress) -> FmrReturn {
+        let mut data = self.heap.get(&address).cloned().unwrap_or_default();
+        data.resize(len, 0);
+
+        // The raw payload precedes the `FmrReturn`, matching the order
+        // `Client::pull`'s default implementation reads them in.
+        self.response.extend(data);
+
+        FmrReturn { value: 0, error: 0 }
+    }
+}
+
+/// Decodes the next packet off the front of `buf`, the inverse of
+/// `FmrPacket::encode`, advancing `buf` past the bytes it consumed.
+fn decode_packet(buf: &mut &[u8]) -> FmrPacket {
+    FmrPacket::decode(buf).expect("a packet built by Client's default methods should decode")
+}
+
+impl Read for VirtualFlipper {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.response.len());
+        for slot in buf[..len].iter_mut() {
+            *slot = self.response.pop_front().unwrap();
+        }
+        Ok(len)
+    }
+}
+
+impl Write for VirtualFlipper {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(Pending::PushData { address, len }) = self.pending.take() {
+            let mut data = buf[..len.min(buf.len())].to_vec();
+            data.resize(len, 0);
+            self.heap.insert(address, data);
+            self.enqueue_return(FmrReturn { value: 0, error: 0 });
+            return Ok(buf.len());
+        }
+
+        // A `Batch` or `CallRecorder` writes several packets back to back
+        // in one `write`; each one gets its own `FmrReturn`, in order.
+        let mut rest = &buf[..];
+        while !rest.is_empty() {
+            let packet = decode_packet(&mut rest);
+
+            if let FmrBody::Push(data) = &packet.body {
+                self.pending = Some(Pending::PushData { address: data.ptr as LfAddress, len: data.len as usize });
+                break;
+            }
+
+            let ret = self.dispatch(&packet);
+            self.enqueue_return(ret);
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Client for VirtualFlipper {
+    fn modules(&mut self) -> &mut Modules {
+        &mut self.modules
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::Args;
+
+    #[test]
+    fn test_call_round_trip() {
+        let mut device = VirtualFlipper::new();
+        device.register("led", 0, |args| {
+            assert_eq!(args.len(), 3);
+            (args[0].value + args[1].value + args[2].value) as LfValue
+        });
+
+        let mut args = Args::new();
+        args.append(10u8).append(20u8).append(30u8);
+        let result = device.invoke("led", 0, LfType::lf_uint8, &args);
+
+        assert_eq!(result, Ok(60));
+    }
+}
@@ -11,7 +11,9 @@ extern crate log;
 #[macro_use]
 extern crate failure;
 extern crate libc;
-extern crate libusb;
+extern crate rusb;
+extern crate embedded_hal;
+extern crate nb;
 
 #[macro_use]
 pub mod macros;
@@ -22,6 +24,9 @@ pub mod carbon;
 
 pub use self::runtime::Client;
 pub use self::runtime::protocol::LfType;
+pub use self::runtime::buffer::LfBuffer;
+pub use self::runtime::trace::{set_tracer, Trace, TraceEvent};
+pub use self::runtime::logging::{configure_level, poll_logs, drain_logs};
 
 use libc::{c_void, c_char};
 use std::ffi::CString;
@@ -0,0 +1,97 @@
+//! A DMA-suitably-aligned, pinned buffer for zero-copy `push`/`pull`
+//! transfers, so callers that care about alignment don't have to bounce
+//! their data through an intermediate `Vec` first.
+
+use std::alloc::{alloc_zeroed, dealloc, Layout};
+use std::ptr::NonNull;
+use std::slice;
+
+/// The alignment Flipper's USB/DMA path assumes pushed and pulled buffers
+/// satisfy. `LfBuffer` allocates to this boundary up front so the
+/// transport can hand its storage straight to the device without bouncing
+/// through an aligned scratch buffer first.
+pub const LF_BUFFER_ALIGN: usize = 32;
+
+/// An owned allocation aligned to `LF_BUFFER_ALIGN`, for use with
+/// `Client::push_buffer`/`Client::pull_buffer`.
+///
+/// The allocation never moves or reallocates for the life of the buffer, so
+/// a pointer borrowed from it (e.g. by an in-flight USB transfer) stays
+/// valid as long as the `LfBuffer` isn't dropped.
+pub struct LfBuffer {
+    ptr: NonNull<u8>,
+    len: usize,
+    layout: Layout,
+}
+
+unsafe impl Send for LfBuffer {}
+
+impl LfBuffer {
+    /// Allocates a new zeroed buffer of `len` bytes, aligned to `LF_BUFFER_ALIGN`.
+    pub fn new(len: usize) -> LfBuffer {
+        let layout = Layout::from_size_align(len.max(1), LF_BUFFER_ALIGN)
+            .expect("buffer length should fit in isize at the required alignment");
+        let ptr = unsafe { alloc_zeroed(layout) };
+        let ptr = NonNull::new(ptr).expect("allocation should succeed");
+        LfBuffer { ptr, len, layout }
+    }
+
+    /// The size of the buffer in bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Borrows the whole buffer for a `push_buffer` call.
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+
+    /// Borrows the whole buffer for a `pull_buffer` call.
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+
+    /// Splits the buffer into contiguous regions of the given sizes, for a
+    /// scatter/gather transfer against `Client::push_regions`/`pull_regions`.
+    ///
+    /// `sizes` must sum to no more than `self.len()`; any remaining tail of
+    /// the buffer is left out of the result.
+    pub fn regions(&self, sizes: &[usize]) -> Vec<&[u8]> {
+        let data = self.as_slice();
+        let mut offset = 0;
+        sizes.iter().map(|&size| {
+            let region = &data[offset..offset + size];
+            offset += size;
+            region
+        }).collect()
+    }
+}
+
+impl Drop for LfBuffer {
+    fn drop(&mut self) {
+        unsafe { dealloc(self.ptr.as_ptr(), self.layout) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffer_is_aligned_and_zeroed() {
+        let buffer = LfBuffer::new(64);
+        assert_eq!(buffer.len(), 64);
+        assert_eq!(buffer.as_slice().as_ptr().align_offset(LF_BUFFER_ALIGN), 0);
+        assert!(buffer.as_slice().iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn test_regions_split_contiguous_buffer() {
+        let mut buffer = LfBuffer::new(6);
+        buffer.as_mut_slice().copy_from_slice(&[1, 2, 3, 4, 5, 6]);
+
+        let regions = buffer.regions(&[2, 4]);
+        assert_eq!(regions[0], &[1, 2]);
+        assert_eq!(regions[1], &[3, 4, 5, 6]);
+    }
+}
@@ -0,0 +1,71 @@
+//! Firmware/module loading subsystem built on the `dyld`/`malloc`/`push`/
+//! `free` FMR packet classes, so a host can deploy user modules instead of
+//! only calling the ones already built into the device.
+
+use super::protocol::{FMR_PAYLOAD_SIZE, LfError, LfPointer};
+use super::Client;
+
+/// Tracks the progress of an in-flight (or most recently attempted) module
+/// load, so a host can query whether a load completed and self-verify
+/// before committing to using the module.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoadState {
+    /// No load has been attempted, or the last one was rolled back.
+    Idle,
+    /// Memory has been allocated on the device and the image is being pushed.
+    InProgress,
+    /// The whole image has been pushed to device memory.
+    Pushed,
+    /// The module has been registered with the device's dyld and can be called.
+    Verified,
+}
+
+/// Allocates device memory, pushes `image` to it in `FMR_PAYLOAD_SIZE`-sized
+/// chunks, then issues a `dyld` to register it as `name`.
+///
+/// `state` steps through `InProgress`, `Pushed` and `Verified` as the load
+/// progresses. On success, the returned `LfPointer` is where the
+/// image was pushed; hang onto it to `free` the memory on rollback. On
+/// failure, there's no pointer to hand back to the caller, so this rolls
+/// back (frees the allocation and resets `state` to `Idle`) itself before
+/// returning the original error.
+pub fn load_module<C: Client + ?Sized>(
+    device: &mut C,
+    name: &str,
+    image: &[u8],
+    state: &mut LoadState,
+) -> Result<LfPointer, LfError> {
+    *state = LoadState::InProgress;
+    let pointer = match device.malloc(image.len() as u32) {
+        Ok(pointer) => pointer,
+        Err(err) => {
+            // Nothing was allocated, so there's nothing to free.
+            *state = LoadState::Idle;
+            return Err(err);
+        }
+    };
+
+    for (i, chunk) in image.chunks(FMR_PAYLOAD_SIZE).enumerate() {
+        let offset = (i * FMR_PAYLOAD_SIZE) as u32;
+        if let Err(err) = device.push(LfPointer(pointer.0 + offset), chunk) {
+            rollback(device, pointer, state);
+            return Err(err);
+        }
+    }
+    *state = LoadState::Pushed;
+
+    if let Err(err) = device.load(name) {
+        rollback(device, pointer, state);
+        return Err(err);
+    }
+    *state = LoadState::Verified;
+
+    Ok(pointer)
+}
+
+/// Frees the device memory a load occupied and resets `state` to `Idle`,
+/// for rolling back a load that failed self-verification.
+pub fn rollback<C: Client + ?Sized>(device: &mut C, pointer: LfPointer, state: &mut LoadState) {
+    let _ = device.free(pointer);
+    *state = LoadState::Idle;
+}
@@ -0,0 +1,96 @@
+//! Host-side drain for the device's ring-buffered log, so firmware
+//! `printf`-style diagnostics show up through the normal `log` crate
+//! sinks instead of being lost on the device UART.
+//!
+//! The device exposes this through an ordinary module named `"log"`, the
+//! same way `Uart0` exposes `"uart0"`: function 0 sets the minimum level,
+//! function 1 polls how many bytes are currently buffered, and function 2
+//! pulls them into a pointer obtained from `malloc`. Each drained record
+//! is framed as a `LogRecord` and decoded with the `ProtoRead` layer.
+
+use super::Client;
+use super::Args;
+use super::protocol::{LfError, LfType, LogRecord};
+
+const LOG_MODULE: &str = "log";
+const FN_CONFIGURE: u8 = 0;
+const FN_POLL: u8 = 1;
+const FN_READ: u8 = 2;
+
+/// Maps a `LogRecord`'s level byte to the `log` crate's own `Level`, since
+/// the device's encoding is smaller than `Level`'s discriminants and isn't
+/// shared with it directly.
+fn level_from_byte(byte: u8) -> log::Level {
+    match byte {
+        0 => log::Level::Error,
+        1 => log::Level::Warn,
+        2 => log::Level::Info,
+        3 => log::Level::Debug,
+        _ => log::Level::Trace,
+    }
+}
+
+fn level_to_byte(level: log::Level) -> u8 {
+    match level {
+        log::Level::Error => 0,
+        log::Level::Warn => 1,
+        log::Level::Info => 2,
+        log::Level::Debug => 3,
+        log::Level::Trace => 4,
+    }
+}
+
+/// Sets the device's minimum log level; records below it are dropped
+/// before they're ever written to the ring buffer.
+pub fn configure_level<T: Client + ?Sized>(device: &mut T, level: log::Level) -> Result<(), LfError> {
+    let mut args = Args::new();
+    args.append(level_to_byte(level));
+    device.invoke(LOG_MODULE, FN_CONFIGURE, LfType::lf_void, &args)?;
+    Ok(())
+}
+
+/// Pulls whatever's currently buffered in one transfer, re-emitting each
+/// record through `log::log!` with its device timestamp attached as a
+/// `timestamp_us` key-value field rather than folded into the message, so
+/// structured-logging sinks can filter/query on it, and returns how many
+/// records were drained.
+pub fn poll_logs<T: Client + ?Sized>(device: &mut T) -> Result<usize, LfError> {
+    let args = Args::new();
+    let available = device.invoke(LOG_MODULE, FN_POLL, LfType::lf_uint32, &args)? as u32;
+    if available == 0 { return Ok(0); }
+
+    let pointer = device.malloc(available)?;
+    let mut args = Args::new();
+    args.append(pointer).append(available);
+
+    let read_result = device.invoke(LOG_MODULE, FN_READ, LfType::lf_void, &args);
+    let mut buffer = vec![0u8; available as usize];
+    let pull_result = read_result.and_then(|_| device.pull(pointer, &mut buffer));
+    let _ = device.free(pointer);
+    pull_result?;
+
+    let mut cursor = &buffer[..];
+    let mut drained = 0;
+    while !cursor.is_empty() {
+        let record = match LogRecord::decode(&mut cursor) {
+            Ok(record) => record,
+            Err(_) => break,
+        };
+        log::log!(level_from_byte(record.level), timestamp_us = record.timestamp_us; "{}", record.message);
+        drained += 1;
+    }
+    Ok(drained)
+}
+
+/// Repeatedly calls `poll_logs` until the device reports nothing buffered,
+/// for a background thread that wants to drain persistently rather than
+/// polling in a loop of its own.
+pub fn drain_logs<T: Client + ?Sized>(device: &mut T) -> Result<usize, LfError> {
+    let mut total = 0;
+    loop {
+        let drained = poll_logs(device)?;
+        if drained == 0 { break; }
+        total += drained;
+    }
+    Ok(total)
+}
@@ -1,13 +1,29 @@
 pub mod protocol;
+pub mod load;
+pub mod buffer;
+pub mod trace;
+pub mod logging;
 
 use self::protocol::*;
+use self::buffer::LfBuffer;
+use self::trace::{trace, TraceEvent};
 
-use std::ptr;
 use std::ops::Deref;
-use std::ffi::CString;
-use std::io::{Read, Write};
+use std::io::{self, Read, Write};
 use std::collections::HashMap;
-use std::os::raw::c_char;
+
+/// Decodes an `FmrReturn`, turning a CRC failure into `LfError::CrcMismatch`
+/// rather than the generic `io::ErrorKind::InvalidData` `FmrReturn::decode`
+/// reports it as. A CRC mismatch is the only `InvalidData` that
+/// `FmrReturn::decode` raises itself; any other read failure is reported as
+/// `LfError::Io` with its kind, except that an `InvalidData` raised by the
+/// reader itself is reported as `CrcMismatch` as well.
+fn decode_return<R: Read + ?Sized>(r: &mut R) -> Result<FmrReturn, LfError> {
+    FmrReturn::decode(r).map_err(|err| match err.kind() {
+        io::ErrorKind::InvalidData => LfError::CrcMismatch,
+        kind => LfError::Io(kind),
+    })
+}
 
 pub trait Client: Read + Write {
     fn modules(&mut self) -> &mut Modules;
@@ -18,64 +34,64 @@ pub trait Client: Read + Write {
         function: LfFunction,
         ret: LfType,
         args: &Args,
-    ) -> Option<u64> {
+    ) -> Result<LfValue, LfError> {
 
-        // Create a call packet
-        let mut packet = FmrPacket::new(FmrClass::call);
-
-        // Write the module index and function arguments into the packet
-        let module = self.load(module).expect("should get module");
+        // Write the module index and function arguments into a call packet
+        let module_index = self.load(module)?;
         let argv: Vec<_> = args.iter().map(|arg| arg.0).collect();
-        create_call(&mut packet, module as u32, function, ret, &argv);
-
-        // Calculate the crc for the packet
-        let len = packet.header.len as u32;
-        let crc = calculate_crc(&packet as *const _ as *const u8, len);
-        packet.header.crc = crc;
+        let call = FmrCall { module: module_index as u8, function, ret, args: argv.clone() };
+        let mut packet = FmrPacket::new(FmrBody::Call(call));
+
+        // Encode the packet, and bail out rather than send a packet that
+        // couldn't be serialized in the first place.
+        let bytes = match packet.encode() {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                trace(TraceEvent::Invoke { module: module.to_string(), function, argt: argv.iter().map(|arg| arg.kind).collect(), ret: None });
+                return Err(LfError::Io(err.kind()));
+            }
+        };
 
         // Send the packet as raw bytes
-        self.write(unsafe { packet.as_bytes() });
+        self.write(&bytes)?;
+
+        // Receive and decode the result
+        let result = decode_return(self)?;
+        if result.error != 0 {
+            trace(TraceEvent::Invoke { module: module.to_string(), function, argt: argv.iter().map(|arg| arg.kind).collect(), ret: None });
+            return Err(LfError::from_code(result.error));
+        }
 
-        // Receive the result as raw bytes
-        let mut result = FmrReturn::new();
-        self.read(unsafe { result.as_bytes_mut() });
+        trace(TraceEvent::Invoke {
+            module: module.to_string(),
+            function,
+            argt: argv.iter().map(|arg| arg.kind).collect(),
+            ret: Some(result.value),
+        });
 
-        Some(result.value)
+        Ok(result.value)
     }
 
     /// Given a module name, returns the index of that module on this device if the module is
-    /// installed. Otherwise, returns none.
-    fn load(&mut self, module: &str) -> Option<u64> {
+    /// installed, or the `LfError` the device/link reported otherwise.
+    fn load(&mut self, module: &str) -> Result<LfValue, LfError> {
         let modules = self.modules();
-        if let Some(module) = modules.find(module) { return Some(module as u64); }
+        if let Some(module) = modules.find(module) { return Ok(module as u64); }
 
-        // Create a dyld packet
-        let mut packet = FmrPacket::new(FmrClass::dyld);
+        // A module name can't round-trip through the NUL-terminated dyld
+        // payload if it contains an embedded NUL itself.
+        if module.as_bytes().contains(&0) { return Err(LfError::Io(io::ErrorKind::InvalidInput)); }
 
-        let module_cstring = match CString::new(module) {
-            Ok(cstr) => cstr,
-            Err(_) => return None,
-        };
-
-        // Copy the module name into the packet
-        let buffer = module_cstring.as_bytes_with_nul();
-        let module_cstr = unsafe { &mut (packet.body.dyld.module) as *mut *mut c_char as *mut u8 };
-        unsafe { ptr::copy(buffer.as_ptr(), module_cstr, buffer.len()) };
-        packet.header.len += buffer.len() as u16;
-
-        // Calculate the crc for the packet
-        let len = packet.header.len as u32;
-        let crc = calculate_crc(&packet as *const _ as *const u8, len);
-        packet.header.crc = crc;
+        let mut packet = FmrPacket::new(FmrBody::Dyld(FmrDyld { module: module.to_string() }));
+        let bytes = packet.encode()?;
 
         // Send the packet as raw bytes
-        self.write(unsafe { packet.as_bytes() });
+        self.write(&bytes)?;
 
-        // Receive the result as raw bytes
-        let mut result = FmrReturn::new();
-        self.read(unsafe { result.as_bytes_mut() });
+        // Receive and decode the result
+        let result = decode_return(self)?;
 
-        if result.error != 0 { return None; }
+        if result.error != 0 { return Err(LfError::from_code(result.error)); }
 
         // Register this module so we don't have to look it up in the future
         let modules = self.modules();
@@ -83,7 +99,7 @@ pub trait Client: Read + Write {
         let module = Module::new(module.to_string(), module_index, 0);
         modules.register(module);
 
-        Some(result.value)
+        Ok(result.value)
     }
 
     /// Pushes a buffer of data to a location in Flipper's memory space.
@@ -94,34 +110,26 @@ pub trait Client: Read + Write {
     /// The data buffer to write must be no larger than the size of the memory allocated from
     /// Flipper. If the pointer being used was obtained using `device.malloc(size)`, then
     /// `data.len()` must be less than or equal to `size`.
-    fn push(&mut self, pointer: LfPointer, data: &[u8]) -> Option<()> {
+    fn push(&mut self, pointer: LfPointer, data: &[u8]) -> Result<(), LfError> {
 
-        // Create a push packet
-        let mut packet = FmrPacket::new(FmrClass::push);
-
-        // Write the length and address of the target memory buffer into the packet
-        unsafe {
-            packet.body.data.len = data.len() as u32;
-            packet.body.data.ptr = pointer.0 as u64;
-        }
-
-        // Calculate the crc for the packet
-        let len = packet.header.len as u32;
-        let crc = calculate_crc(&packet as *const _ as *const u8, len);
-        packet.header.crc = crc;
+        // Create a push packet carrying the length and address of the
+        // target memory buffer
+        let body = FmrPushPull { len: data.len() as u32, ptr: pointer.0 as u64 };
+        let mut packet = FmrPacket::new(FmrBody::Push(body));
+        let bytes = packet.encode()?;
 
         // Write the packet as raw bytes
-        self.write(unsafe { packet.as_bytes() });
+        self.write(&bytes)?;
 
         // Write the push payload as raw bytes
-        self.write(data);
+        self.write(data)?;
 
-        // Read the result as raw bytes
-        let mut result = FmrReturn::new();
-        self.read(unsafe { result.as_bytes_mut() });
+        // Receive and decode the result
+        let result = decode_return(self)?;
 
-        if result.error != 0 { return None; }
-        Some(())
+        trace(TraceEvent::Push { pointer: pointer.0, len: data.len(), ok: result.error == 0 });
+        if result.error != 0 { return Err(LfError::from_code(result.error)); }
+        Ok(())
     }
 
     /// Pulls a buffer of data from a location in Flipper's memory space.
@@ -132,88 +140,311 @@ pub trait Client: Read + Write {
     /// The local buffer to write to must be no larger than the size of the memory allocated from
     /// Flipper. If the pointer being used was obtained using `device.malloc(size)`, then
     /// `data.len()` must be less than or equal to `size`.
-    fn pull(&mut self, pointer: LfPointer, buffer: &mut [u8]) -> Option<()> {
+    fn pull(&mut self, pointer: LfPointer, buffer: &mut [u8]) -> Result<(), LfError> {
 
-        // Create a pull packet
-        let mut packet = FmrPacket::new(FmrClass::pull);
-
-        // Write the length and address of the target memory buffer into the packet
-        unsafe {
-            packet.body.data.len = buffer.len() as u32;
-            packet.body.data.ptr = pointer.0 as u64;
-        }
-
-        // Calculate the crc for the packet
-        let len = packet.header.len as u32;
-        let crc = calculate_crc(&packet as *const _ as *const u8, len);
-        packet.header.crc = crc;
+        // Create a pull packet carrying the length and address of the
+        // target memory buffer
+        let body = FmrPushPull { len: buffer.len() as u32, ptr: pointer.0 as u64 };
+        let mut packet = FmrPacket::new(FmrBody::Pull(body));
+        let bytes = packet.encode()?;
 
         // Write the packet as raw bytes
-        self.write(unsafe { packet.as_bytes() });
+        self.write(&bytes)?;
 
         // Read the pull payload as raw bytes
-        self.read(buffer);
+        self.read(buffer)?;
 
-        // Read the result as raw bytes
-        let mut result = FmrReturn::new();
-        self.read(unsafe { result.as_bytes_mut() });
+        // Receive and decode the result
+        let result = decode_return(self)?;
 
-        if result.error != 0 { return None; }
-        Some(())
+        trace(TraceEvent::Pull { pointer: pointer.0, len: buffer.len(), ok: result.error == 0 });
+        if result.error != 0 { return Err(LfError::from_code(result.error)); }
+        Ok(())
     }
 
     /// Allocates a buffer of data of the given size in Flipper's memory space.
-    fn malloc(&mut self, size: u32) -> Option<LfPointer> {
+    fn malloc(&mut self, size: u32) -> Result<LfPointer, LfError> {
 
-        // Create a malloc packet
-        let mut packet = FmrPacket::new(FmrClass::malloc);
+        // Create a malloc packet carrying the size of the requested buffer
+        let body = FmrMemory { size, ptr: 0 };
+        let mut packet = FmrPacket::new(FmrBody::Malloc(body));
+        let bytes = packet.encode()?;
 
-        // Write the size of the requested buffer in the packet
-        unsafe {
-            packet.body.memory.size = size;
-        }
+        // Send the packet as raw bytes
+        self.write(&bytes)?;
+
+        // Receive and decode the result
+        let result = decode_return(self)?;
+
+        if result.error != 0 { return Err(LfError::from_code(result.error)); }
+        Ok(LfPointer(result.value as u32))
+    }
 
-        // Calculate the crc for the packet
-        let len = packet.header.len as u32;
-        let crc = calculate_crc(&packet as *const _ as *const u8, len);
-        packet.header.crc = crc;
+    /// Frees a buffer of memory in Flipper's memory space.
+    fn free(&mut self, pointer: LfPointer) -> Result<(), LfError> {
+
+        // Create a free packet carrying the address of the buffer to free
+        let body = FmrMemory { size: 0, ptr: pointer.0 as u64 };
+        let mut packet = FmrPacket::new(FmrBody::Free(body));
+        let bytes = packet.encode()?;
 
         // Send the packet as raw bytes
-        self.write(unsafe { packet.as_bytes() });
+        self.write(&bytes)?;
 
-        // Read the result as raw bytes
-        let mut result = FmrReturn::new();
-        self.read(unsafe { result.as_bytes_mut() });
+        // Receive and decode the result
+        let result = decode_return(self)?;
 
-        if result.error != 0 { return None; }
-        Some(LfPointer(result.value as u32))
+        if result.error != 0 { return Err(LfError::from_code(result.error)); }
+        Ok(())
     }
 
-    /// Frees a buffer of memory in Flipper's memory space.
-    fn free(&mut self, pointer: LfPointer) -> Option<()> {
+    /// Pushes an entire `LfBuffer` to `pointer` in one transfer.
+    ///
+    /// Unlike `push`, the source is guaranteed to be aligned to
+    /// `buffer::LF_BUFFER_ALIGN`, which matters when the underlying
+    /// USB/DMA path requires aligned, pinned memory rather than an
+    /// arbitrary `&[u8]` that may have come from anywhere.
+    fn push_buffer(&mut self, pointer: LfPointer, buffer: &LfBuffer) -> Result<(), LfError> {
+        self.push(pointer, buffer.as_slice())
+    }
 
-        // Create a free packet
-        let mut packet = FmrPacket::new(FmrClass::free);
+    /// Pulls into an entire `LfBuffer` from `pointer` in one transfer. See
+    /// `push_buffer` for the alignment guarantee this relies on.
+    fn pull_buffer(&mut self, pointer: LfPointer, buffer: &mut LfBuffer) -> Result<(), LfError> {
+        self.pull(pointer, buffer.as_mut_slice())
+    }
 
-        // Write the address of the buffer to free into the packet
-        unsafe {
-            packet.body.memory.ptr = pointer.0 as u64;
+    /// Pushes several non-contiguous regions to one logical `LfPointer`
+    /// range, as if they were a single contiguous buffer: each region is
+    /// written to sequential device addresses computed from the lengths of
+    /// the regions before it, so a caller with scattered source data
+    /// doesn't have to gather it into one allocation first.
+    fn push_regions(&mut self, pointer: LfPointer, regions: &[&[u8]]) -> Result<(), LfError> {
+        let mut offset = 0u32;
+        for region in regions {
+            self.push(LfPointer(pointer.0 + offset), region)?;
+            offset += region.len() as u32;
         }
+        Ok(())
+    }
 
-        // Calculate the crc for the packet
-        let len = packet.header.len as u32;
-        let crc = calculate_crc(&packet as *const _ as *const u8, len);
-        packet.header.crc = crc;
+    /// Pulls one logical `LfPointer` range into several non-contiguous
+    /// regions, the inverse of `push_regions`.
+    fn pull_regions(&mut self, pointer: LfPointer, regions: &mut [&mut [u8]]) -> Result<(), LfError> {
+        let mut offset = 0u32;
+        for region in regions {
+            self.pull(LfPointer(pointer.0 + offset), region)?;
+            offset += region.len() as u32;
+        }
+        Ok(())
+    }
 
-        // Send the packet as raw bytes
-        self.write(unsafe { packet.as_bytes() });
+    /// Starts a batch of invocations that will be sent as a single USB
+    /// transfer when flushed, instead of a full request/response round-trip
+    /// per call.
+    fn batch(&mut self) -> Batch<Self> where Self: Sized {
+        Batch { device: self, calls: Vec::new() }
+    }
+
+    /// Starts recording a sequence of invocations, serializing each one's
+    /// packet (CRC included) as it's queued rather than when the recording
+    /// is sent. Unlike `batch`, the result can be frozen into a
+    /// `RecordedCalls` and replayed again later without re-serializing or
+    /// re-computing a single CRC, which matters for a loop that sends the
+    /// same sequence of calls (e.g. streaming LED animation frames) many
+    /// times in a row.
+    fn record(&mut self) -> CallRecorder<Self> where Self: Sized {
+        CallRecorder { device: self, buffer: Vec::new(), rets: Vec::new() }
+    }
+}
+
+/// One invocation queued on a `Batch`, awaiting `Batch::flush`.
+struct QueuedCall {
+    module: String,
+    function: LfFunction,
+    ret: LfType,
+    args: Args,
+}
+
+/// A builder that queues `(module, function, ret, args)` invocations and
+/// sends them as one USB transfer, reading back one return value per
+/// non-`lf_void` call in submission order.
+///
+/// This mirrors coalescing many small RPC packets into one send (and
+/// skipping the per-call wait for an ack): every queued call is serialized
+/// into a single buffer and written once, then the response stream is
+/// decoded using each call's own expected return type, since a `lf_void`
+/// call has no return slot to read.
+pub struct Batch<'a, T: Client + ?Sized> {
+    device: &'a mut T,
+    calls: Vec<QueuedCall>,
+}
+
+impl<'a, T: Client + ?Sized> Batch<'a, T> {
+    /// Queues an invocation; nothing is sent until `flush` is called.
+    pub fn invoke(mut self, module: &str, function: LfFunction, ret: LfType, args: Args) -> Self {
+        self.calls.push(QueuedCall { module: module.to_string(), function, ret, args });
+        self
+    }
+
+    /// Sends every queued invocation in a single write, then reads back one
+    /// return value per non-`lf_void` call. A call whose module can't be
+    /// resolved, or whose arguments don't fit in a packet, reports that
+    /// specific `LfError` without affecting any other call's result. If the
+    /// write itself fails, every call reports that `LfError` instead.
+    pub fn flush(self) -> Vec<Result<LfValue, LfError>> {
+        enum Pending {
+            Sent(LfType),
+            Failed(LfError),
+        }
+
+        let Batch { device, calls } = self;
+
+        let mut buffer = Vec::new();
+        let mut pending = Vec::with_capacity(calls.len());
+
+        for call in &calls {
+            let module = match device.load(&call.module) {
+                Ok(module) => module,
+                Err(err) => { pending.push(Pending::Failed(err)); continue; }
+            };
 
-        // Read the result as raw bytes
-        let mut result = FmrReturn::new();
-        self.read(unsafe { result.as_bytes_mut() });
+            let argv: Vec<_> = call.args.iter().map(|arg| arg.0).collect();
+            let fmr_call = FmrCall { module: module as u8, function: call.function, ret: call.ret, args: argv };
+            let mut packet = FmrPacket::new(FmrBody::Call(fmr_call));
+            let bytes = match packet.encode() {
+                Ok(bytes) => bytes,
+                Err(err) => { pending.push(Pending::Failed(LfError::from(err))); continue; }
+            };
 
-        if result.error != 0 { return None; }
-        Some(())
+            buffer.extend_from_slice(&bytes);
+            pending.push(Pending::Sent(call.ret));
+        }
+
+        if let Err(err) = device.write(&buffer) {
+            let err = LfError::from(err);
+            return pending.into_iter().map(|_| Err(err)).collect();
+        }
+
+        pending.into_iter().map(|entry| match entry {
+            Pending::Sent(LfType::lf_void) => Ok(0),
+            Pending::Sent(_) => {
+                match decode_return(device) {
+                    Ok(result) if result.error == 0 => Ok(result.value),
+                    Ok(result) => Err(LfError::from_code(result.error)),
+                    Err(err) => Err(err),
+                }
+            }
+            Pending::Failed(err) => Err(err),
+        }).collect()
+    }
+}
+
+/// One call queued on a `CallRecorder`: its packet (CRC included) is
+/// already serialized into the recorder's buffer, so this only remembers
+/// whether (and how) to read back a return value for it.
+#[derive(Debug, Copy, Clone)]
+enum RecordedReturn {
+    /// The module couldn't be resolved (or the call didn't fit in a
+    /// packet) when it was recorded, so there's no return to read back.
+    Failed,
+    /// A `lf_void` call has no return slot; its recorded result is always
+    /// `Ok(0)`.
+    Void,
+    /// Read one `FmrReturn` for this call, in submission order.
+    Expect,
+}
+
+/// Queues `(module, function, ret, args)` invocations like `Batch`, except
+/// each call is serialized into a growing buffer (CRC included) as soon as
+/// it's recorded instead of when the recording is sent. This lets the
+/// buffer be frozen with `finish` into a `RecordedCalls` and replayed many
+/// times over without paying to re-serialize or re-CRC a single packet.
+pub struct CallRecorder<'a, T: Client + ?Sized> {
+    device: &'a mut T,
+    buffer: Vec<u8>,
+    rets: Vec<RecordedReturn>,
+}
+
+impl<'a, T: Client + ?Sized> CallRecorder<'a, T> {
+    /// Resolves `module` against the device's already-loaded modules and
+    /// serializes the call's packet immediately. A module that hasn't been
+    /// `load`ed yet records as `RecordedReturn::Failed` rather than issuing
+    /// a `load` round-trip of its own, since that would defeat the point
+    /// of recording calls to send without blocking.
+    pub fn invoke(mut self, module: &str, function: LfFunction, ret: LfType, args: &Args) -> Self {
+        let module_index = match self.device.modules().find(module) {
+            Some(index) => index,
+            None => { self.rets.push(RecordedReturn::Failed); return self; }
+        };
+
+        let argv: Vec<_> = args.iter().map(|arg| arg.0).collect();
+        let call = FmrCall { module: module_index as u8, function, ret, args: argv };
+        let mut packet = FmrPacket::new(FmrBody::Call(call));
+
+        match packet.encode() {
+            Ok(bytes) => {
+                self.buffer.extend_from_slice(&bytes);
+                self.rets.push(if let LfType::lf_void = ret { RecordedReturn::Void } else { RecordedReturn::Expect });
+            }
+            Err(_) => self.rets.push(RecordedReturn::Failed),
+        }
+
+        self
+    }
+
+    /// Sends the recorded buffer in a single write, then reads back one
+    /// return value per call recorded with `RecordedReturn::Expect`, in
+    /// submission order.
+    pub fn flush(self) -> Vec<Result<LfValue, LfError>> {
+        let CallRecorder { device, buffer, rets } = self;
+        replay_recording(device, &buffer, &rets)
+    }
+
+    /// Freezes this recording so its buffer can be replayed later via
+    /// `RecordedCalls::replay` without re-serializing or re-CRCing any of
+    /// its calls.
+    pub fn finish(self) -> RecordedCalls {
+        RecordedCalls { buffer: self.buffer, rets: self.rets }
+    }
+}
+
+/// Sends `buffer` to `device` in one write, then reads back `rets.len()`
+/// results, shared between `CallRecorder::flush` and `RecordedCalls::replay`
+/// since both send the same kind of pre-serialized buffer the same way.
+fn replay_recording<T: Client + ?Sized>(device: &mut T, buffer: &[u8], rets: &[RecordedReturn]) -> Vec<Result<LfValue, LfError>> {
+    if let Err(err) = device.write(buffer) {
+        let err = LfError::from(err);
+        return rets.iter().map(|_| Err(err)).collect();
+    }
+
+    rets.iter().map(|ret| match ret {
+        RecordedReturn::Failed => Err(LfError::UnknownModule),
+        RecordedReturn::Void => Ok(0),
+        RecordedReturn::Expect => {
+            match decode_return(device) {
+                Ok(result) if result.error == 0 => Ok(result.value),
+                Ok(result) => Err(LfError::from_code(result.error)),
+                Err(err) => Err(err),
+            }
+        }
+    }).collect()
+}
+
+/// A `CallRecorder`'s buffer and return bookkeeping, captured once so the
+/// same sequence of calls can be sent again without re-serializing or
+/// re-computing a single CRC.
+pub struct RecordedCalls {
+    buffer: Vec<u8>,
+    rets: Vec<RecordedReturn>,
+}
+
+impl RecordedCalls {
+    /// Re-sends this recording's buffer to `device` in one write, then
+    /// reads back one return value per call that was resolvable when it
+    /// was recorded, in submission order.
+    pub fn replay<T: Client + ?Sized>(&self, device: &mut T) -> Vec<Result<LfValue, LfError>> {
+        replay_recording(device, &self.buffer, &self.rets)
     }
 }
 
@@ -410,89 +641,80 @@ impl From<LfReturn> for LfPointer {
     }
 }
 
-pub fn create_call(
-    packet: &mut FmrPacket,
-    module: LfModule,
-    function: LfFunction,
-    return_type: LfType,
-    args: &[LfArg],
-) -> Result<(), ()> {
-    let argc = args.len() as LfArgc;
-
-    let mut offset = unsafe {
-        // Populate call packet
-        packet.body.call.module = module as u8;
-        packet.body.call.function = function;
-        packet.body.call.ret = return_type;
-        packet.body.call.argc = argc;
-
-        // Take the offset to the base of the argument list
-        &mut packet.body.call.argv as *mut () as *mut u8
-    };
-
-    // Copy each argument into the call packet
-    for i in 0..argc {
-        let arg: &LfArg = args.get(i as usize).ok_or(())?;
-        unsafe {
-            packet.body.call.argt |= (((arg.kind as u8) & LfType::MAX) as u32) << (i * 4);
-
-            // Copy the argument value into the call packet
-            let arg_size = arg.kind.size();
-            let arg_value_address = &arg.value as *const u64;
-            ptr::copy(arg_value_address as *const u8, offset, arg_size);
-
-            // Increase the offset and size of the packet by the size of this argument
-            offset = offset.add(arg_size);
-            packet.header.len += arg_size as u16;
-        }
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::virtual_device::VirtualFlipper;
 
-    Ok(())
-}
+    #[test]
+    fn test_call_packet_round_trip_from_args() {
+        let mut args = Args::new();
+        args.append(10u8).append(1000u16).append(2000u32).append(4000u64);
+        let argv: Vec<_> = args.iter().map(|arg| arg.0).collect();
 
-/// Given a memory buffer and a length, generates a CRC of the data in the buffer.
-pub fn calculate_crc(data: *const u8, length: u32) -> u16 {
-    const POLY: u16 = 0x1021;
-    let mut crc: u16 = 0;
-    for i in 0..length {
-        unsafe {
-            let word = ptr::read(data.offset(i as isize) as *const u16);
-            crc = crc ^ word << 8;
-            for _ in 0..8 {
-                if crc & 0x8000 != 0 {
-                    crc = crc << 1 ^ POLY;
-                } else {
-                    crc = crc << 1;
-                }
+        let call = FmrCall { module: 3, function: 5, ret: LfType::lf_void, args: argv };
+        let mut packet = FmrPacket::new(FmrBody::Call(call));
+        let bytes = packet.encode().expect("call packet should encode");
+
+        let decoded = FmrPacket::decode(&mut &bytes[..]).expect("call packet should decode");
+        match decoded.body {
+            FmrBody::Call(call) => {
+                assert_eq!(call.module, 3);
+                assert_eq!(call.function, 5);
+                assert_eq!(call.args.iter().map(|arg| arg.value).collect::<Vec<_>>(), vec![10, 1000, 2000, 4000]);
             }
+            _ => panic!("expected a call body"),
         }
     }
-    crc
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_batch_flushes_every_queued_call_and_matches_calls_to_returns() {
+        let mut device = VirtualFlipper::new();
+        device.register("led", 0, |args| (args[0].value + args[1].value) as LfValue);
+        device.register("led", 1, |args| args[0].value as LfValue);
+
+        let mut sum_args = Args::new();
+        sum_args.append(2u8).append(3u8);
+        let mut echo_args = Args::new();
+        echo_args.append(9u8);
+
+        let results = device.batch()
+            .invoke("led", 0, LfType::lf_uint8, sum_args)
+            .invoke("led", 1, LfType::lf_uint8, echo_args)
+            .flush();
+
+        assert_eq!(results, vec![Ok(5), Ok(9)]);
+    }
 
     #[test]
-    fn test_create_call() {
-        let args = vec![
-            LfArg { kind: LfType::lf_uint8, value: 10 },
-            LfArg { kind: LfType::lf_uint16, value: 1000 },
-            LfArg { kind: LfType::lf_uint32, value: 2000 },
-            LfArg { kind: LfType::lf_uint64, value: 4000 },
-        ];
-
-        let mut packet = FmrPacket::new(FmrClass::call);
-        let mut call_packet = unsafe { packet.into_call() };
-        create_call(&mut call_packet, 3, 5, LfType::lf_void, &args);
-
-        let payload = unsafe { packet.base.payload };
-        for chunk in payload.chunks(8) {
-            for byte in chunk {
-                print!("{:02X} ", byte);
-            }
-            println!();
-        }
+    fn test_batch_reports_one_calls_error_without_failing_the_others() {
+        let mut device = VirtualFlipper::new();
+        device.register("led", 0, |args| args[0].value as LfValue);
+
+        let mut known_args = Args::new();
+        known_args.append(4u8);
+
+        // "missing" has no registered handler, so its call fails on the
+        // device side; the sibling call to "led" should still succeed.
+        let results = device.batch()
+            .invoke("missing", 0, LfType::lf_uint8, Args::new())
+            .invoke("led", 0, LfType::lf_uint8, known_args)
+            .flush();
+
+        assert_eq!(results, vec![Err(LfError::NoHandler), Ok(4)]);
+    }
+
+    #[test]
+    fn test_recorded_calls_replay_resends_the_same_buffer() {
+        let mut device = VirtualFlipper::new();
+        device.register("led", 0, |args| args[0].value as LfValue);
+        device.load("led").expect("should load led module");
+
+        let mut args = Args::new();
+        args.append(7u8);
+        let recording = device.record().invoke("led", 0, LfType::lf_uint8, &args).finish();
+
+        assert_eq!(recording.replay(&mut device), vec![Ok(7)]);
+        assert_eq!(recording.replay(&mut device), vec![Ok(7)]);
     }
 }
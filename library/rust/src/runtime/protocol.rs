@@ -1,16 +1,20 @@
-use std::slice;
-use std::mem::size_of;
-use std::os::raw::c_char;
-use std::fmt::{self as fmt, Debug};
+use std::io::{self as io, Read, Write};
 
 pub const FMR_MAGIC_NUMBER: u8 = 0xFE;
 pub const FMR_PACKET_SIZE: usize = 64;
-pub const FMR_PAYLOAD_SIZE: usize = FMR_PACKET_SIZE - size_of::<FmrHeader>();
 
-#[derive(Copy, Clone)]
-#[repr(C, packed)]
-pub struct FmrPayload(pub [u8; FMR_PAYLOAD_SIZE]);
-const FMR_PAYLOAD_EMPTY: FmrPayload = FmrPayload([0; FMR_PAYLOAD_SIZE]);
+/// The on-wire size of an encoded `FmrHeader`: magic(1) + crc(2) + len(2) +
+/// class(1). This used to be computed from `size_of::<FmrHeader>()`, but the
+/// `#[repr(C, packed)]` struct it described rounded up to 8 under the C
+/// layout rules; now that `FmrHeader::encode` writes each field explicitly
+/// there's no padding to account for, so this is simply the sum of the wire
+/// sizes below.
+pub const FMR_HEADER_SIZE: usize = 6;
+
+/// The largest class-specific body that fits in one `FMR_PACKET_SIZE`-sized
+/// packet, used to chunk a module image for `load::load_module`'s `push`
+/// calls.
+pub const FMR_PAYLOAD_SIZE: usize = FMR_PACKET_SIZE - FMR_HEADER_SIZE;
 
 pub type LfCrc = u16;
 pub type LfTypes = u32;
@@ -21,10 +25,10 @@ pub type LfModule = u32;
 pub type LfFunction = u8;
 pub type LfAddress = u32;
 
-#[derive(Copy, Clone)]
+#[derive(Debug, Copy, Clone)]
 pub struct LfPointer(pub(crate) LfAddress);
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 #[repr(u8)]
 pub enum FmrClass {
     call = 0,
@@ -35,8 +39,89 @@ pub enum FmrClass {
     free = 5,
 }
 
+impl FmrClass {
+    pub fn from(byte: u8) -> Option<FmrClass> {
+        match byte {
+            0 => Some(FmrClass::call),
+            1 => Some(FmrClass::push),
+            2 => Some(FmrClass::pull),
+            3 => Some(FmrClass::dyld),
+            4 => Some(FmrClass::malloc),
+            5 => Some(FmrClass::free),
+            _ => None,
+        }
+    }
+}
+
+/// A minimal little-endian wire-format writer for the FMR protocol,
+/// implemented for anything that implements `io::Write`.
+///
+/// This replaces casting packet structs to `&[u8]` through raw pointers:
+/// every field is written one at a time, in order, so there's no reliance on
+/// a particular `#[repr]` layout matching the wire format byte-for-byte.
+pub trait ProtoWrite: Write {
+    fn write_u8(&mut self, value: u8) -> io::Result<()> {
+        self.write_all(&[value])
+    }
+
+    fn write_u16(&mut self, value: u16) -> io::Result<()> {
+        self.write_all(&value.to_le_bytes())
+    }
+
+    fn write_u32(&mut self, value: u32) -> io::Result<()> {
+        self.write_all(&value.to_le_bytes())
+    }
+
+    fn write_u64(&mut self, value: u64) -> io::Result<()> {
+        self.write_all(&value.to_le_bytes())
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.write_all(bytes)
+    }
+}
+
+impl<W: Write + ?Sized> ProtoWrite for W {}
+
+/// The `ProtoWrite` counterpart for reading a packet back out of anything
+/// that implements `io::Read`.
+pub trait ProtoRead: Read {
+    fn read_u8(&mut self) -> io::Result<u8> {
+        let mut buf = [0u8; 1];
+        self.read_exact(&mut buf)?;
+        Ok(buf[0])
+    }
+
+    fn read_u16(&mut self) -> io::Result<u16> {
+        let mut buf = [0u8; 2];
+        self.read_exact(&mut buf)?;
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    fn read_u32(&mut self) -> io::Result<u32> {
+        let mut buf = [0u8; 4];
+        self.read_exact(&mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn read_u64(&mut self) -> io::Result<u64> {
+        let mut buf = [0u8; 8];
+        self.read_exact(&mut buf)?;
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    fn read_bytes(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        self.read_exact(buf)
+    }
+}
+
+impl<R: Read + ?Sized> ProtoRead for R {}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
 #[derive(Debug, Copy, Clone)]
-#[repr(C, packed)]
 pub struct FmrHeader {
     pub magic: u8,
     pub crc: LfCrc,
@@ -44,84 +129,313 @@ pub struct FmrHeader {
     pub class: FmrClass,
 }
 
-#[derive(Debug, Copy, Clone)]
-#[repr(C, packed)]
+impl FmrHeader {
+    pub fn new(class: FmrClass) -> FmrHeader {
+        FmrHeader { magic: FMR_MAGIC_NUMBER, crc: 0, len: 0, class }
+    }
+
+    pub fn encode<W: ProtoWrite + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        w.write_u8(self.magic)?;
+        w.write_u16(self.crc)?;
+        w.write_u16(self.len)?;
+        w.write_u8(self.class as u8)?;
+        Ok(())
+    }
+
+    pub fn decode<R: ProtoRead + ?Sized>(r: &mut R) -> io::Result<FmrHeader> {
+        let magic = r.read_u8()?;
+        let crc = r.read_u16()?;
+        let len = r.read_u16()?;
+        let class = FmrClass::from(r.read_u8()?).ok_or_else(|| invalid_data("unrecognized FmrClass"))?;
+        Ok(FmrHeader { magic, crc, len, class })
+    }
+}
+
+/// The class-specific body of an `FmrPacket`. Replaces the old
+/// `#[repr(C, packed)]` union of payload structs: since every variant now
+/// encodes/decodes its own fields explicitly, there's no need for the
+/// variants to share a common memory layout.
+#[derive(Debug, Clone)]
+pub enum FmrBody {
+    Call(FmrCall),
+    Push(FmrPushPull),
+    Pull(FmrPushPull),
+    Dyld(FmrDyld),
+    Malloc(FmrMemory),
+    Free(FmrMemory),
+}
+
+impl FmrBody {
+    pub fn class(&self) -> FmrClass {
+        match self {
+            FmrBody::Call(_) => FmrClass::call,
+            FmrBody::Push(_) => FmrClass::push,
+            FmrBody::Pull(_) => FmrClass::pull,
+            FmrBody::Dyld(_) => FmrClass::dyld,
+            FmrBody::Malloc(_) => FmrClass::malloc,
+            FmrBody::Free(_) => FmrClass::free,
+        }
+    }
+
+    pub fn encode<W: ProtoWrite + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        match self {
+            FmrBody::Call(call) => call.encode(w),
+            FmrBody::Push(data) | FmrBody::Pull(data) => data.encode(w),
+            FmrBody::Dyld(dyld) => dyld.encode(w),
+            FmrBody::Malloc(memory) | FmrBody::Free(memory) => memory.encode(w),
+        }
+    }
+
+    pub fn decode<R: ProtoRead + ?Sized>(class: FmrClass, r: &mut R) -> io::Result<FmrBody> {
+        Ok(match class {
+            FmrClass::call => FmrBody::Call(FmrCall::decode(r)?),
+            FmrClass::push => FmrBody::Push(FmrPushPull::decode(r)?),
+            FmrClass::pull => FmrBody::Pull(FmrPushPull::decode(r)?),
+            FmrClass::dyld => FmrBody::Dyld(FmrDyld::decode(r)?),
+            FmrClass::malloc => FmrBody::Malloc(FmrMemory::decode(r)?),
+            FmrClass::free => FmrBody::Free(FmrMemory::decode(r)?),
+        })
+    }
+}
+
+/// A complete FMR packet: a fixed `FmrHeader` followed by a class-specific
+/// body, whose combined length and CRC-16/CCITT-FALSE checksum are computed
+/// from what `encode` actually writes rather than a hardcoded size.
+#[derive(Debug, Clone)]
 pub struct FmrPacket {
     pub header: FmrHeader,
     pub body: FmrBody,
 }
 
-#[derive(Copy, Clone)]
-#[repr(C, packed)]
-pub union FmrBody {
-    pub base: FmrPayload,
-    pub call: FmrCall,
-    pub data: FmrPushPull,
-    pub dyld: FmrDyld,
-    pub memory: FmrMemory,
-}
-
 impl FmrPacket {
-    pub fn new(class: FmrClass) -> FmrPacket {
-        FmrPacket {
-            header: FmrHeader {
-                magic: FMR_MAGIC_NUMBER,
-                crc: 0,
-                // Under normal circumstances this would be mem::size_of::<FmrHeader>(),
-                // but for some reason the packed repr in C calculates the size as 8, not 6.
-                len: 8,
-                class,
-            },
-            body: FmrBody {
-                base: FMR_PAYLOAD_EMPTY,
-            }
-        }
+    pub fn new(body: FmrBody) -> FmrPacket {
+        FmrPacket { header: FmrHeader::new(body.class()), body }
     }
 
-    #[allow(dead_code)]
-    pub unsafe fn as_bytes(&self) -> &[u8] {
-        slice::from_raw_parts(self as *const _ as *const u8, size_of::<Self>())
+    /// Encodes the header and body to their wire bytes, filling in
+    /// `header.len` and `header.crc` (over the whole packet, with the CRC
+    /// field itself treated as zero) from what was actually written.
+    pub fn encode(&mut self) -> io::Result<Vec<u8>> {
+        let mut body_bytes = Vec::new();
+        self.body.encode(&mut body_bytes)?;
+
+        self.header.len = (FMR_HEADER_SIZE + body_bytes.len()) as u16;
+        self.header.crc = 0;
+
+        let mut bytes = Vec::with_capacity(self.header.len as usize);
+        self.header.encode(&mut bytes)?;
+        bytes.extend_from_slice(&body_bytes);
+
+        self.header.crc = calculate_crc(&bytes);
+        bytes[1..3].copy_from_slice(&self.header.crc.to_le_bytes());
+
+        Ok(bytes)
+    }
+
+    /// Decodes a packet from `r`: the header first, then exactly
+    /// `header.len - FMR_HEADER_SIZE` body bytes, dispatched to the right
+    /// `FmrBody` variant by `header.class`.
+    pub fn decode<R: ProtoRead + ?Sized>(r: &mut R) -> io::Result<FmrPacket> {
+        let header = FmrHeader::decode(r)?;
+        let body_len = (header.len as usize)
+            .checked_sub(FMR_HEADER_SIZE)
+            .ok_or_else(|| invalid_data("header.len shorter than the header itself"))?;
+
+        let mut body_bytes = vec![0u8; body_len];
+        r.read_bytes(&mut body_bytes)?;
+        let body = FmrBody::decode(header.class, &mut &body_bytes[..])?;
+
+        Ok(FmrPacket { header, body })
     }
 
-    #[allow(dead_code)]
-    pub unsafe fn as_bytes_mut(&mut self) -> &mut [u8] {
-        slice::from_raw_parts_mut(self as *mut _ as *mut u8, size_of::<Self>())
+    /// Recomputes the CRC over this packet's header as it stands (`len`
+    /// included, with the CRC field treated as zero) followed by its encoded
+    /// body, and compares it against `header.crc`, returning `true` if they
+    /// match. Used to catch corruption in a packet that's just been decoded
+    /// off the wire.
+    pub fn verify(&self) -> io::Result<bool> {
+        let mut header = self.header;
+        header.crc = 0;
+
+        let mut bytes = Vec::new();
+        header.encode(&mut bytes)?;
+        self.body.encode(&mut bytes)?;
+        Ok(calculate_crc(&bytes) == self.header.crc)
     }
 }
 
-#[derive(Debug, Copy, Clone)]
-#[repr(C, packed)]
+#[derive(Debug, Clone)]
 pub struct FmrCall {
     pub module: u8,
-    pub function: u8,
+    pub function: LfFunction,
     pub ret: LfType,
-    pub argt: LfTypes,
-    pub argc: LfArgc,
-    pub argv: (),
+    pub args: Vec<LfArg>,
+}
+
+impl FmrCall {
+    pub fn encode<W: ProtoWrite + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        w.write_u8(self.module)?;
+        w.write_u8(self.function)?;
+        w.write_u8(self.ret as u8)?;
+
+        let mut argt: LfTypes = 0;
+        for (i, arg) in self.args.iter().enumerate() {
+            argt |= (((arg.kind as u8) & LfType::MAX) as u32) << (i as u32 * 4);
+        }
+        w.write_u32(argt)?;
+        w.write_u8(self.args.len() as LfArgc)?;
+
+        for arg in &self.args {
+            w.write_bytes(&arg.value.to_le_bytes()[..arg.kind.size()])?;
+        }
+        Ok(())
+    }
+
+    pub fn decode<R: ProtoRead + ?Sized>(r: &mut R) -> io::Result<FmrCall> {
+        let module = r.read_u8()?;
+        let function = r.read_u8()?;
+        let ret = LfType::from(r.read_u8()?).ok_or_else(|| invalid_data("unrecognized return LfType"))?;
+        let argt = r.read_u32()?;
+        let argc = r.read_u8()?;
+
+        let mut args = Vec::with_capacity(argc as usize);
+        for i in 0..argc {
+            let kind_byte = ((argt >> (i as u32 * 4)) & LfType::MAX as u32) as u8;
+            let kind = LfType::from(kind_byte).ok_or_else(|| invalid_data("unrecognized argument LfType"))?;
+
+            let mut buf = [0u8; 8];
+            r.read_bytes(&mut buf[..kind.size()])?;
+            args.push(LfArg { kind, value: u64::from_le_bytes(buf) });
+        }
+
+        Ok(FmrCall { module, function, ret, args })
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
-#[repr(C, packed)]
 pub struct FmrPushPull {
     pub len: u32,
     pub ptr: u64,
 }
 
-#[derive(Debug, Copy, Clone)]
-#[repr(C, packed)]
+impl FmrPushPull {
+    pub fn encode<W: ProtoWrite + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        w.write_u32(self.len)?;
+        w.write_u64(self.ptr)?;
+        Ok(())
+    }
+
+    pub fn decode<R: ProtoRead + ?Sized>(r: &mut R) -> io::Result<FmrPushPull> {
+        let len = r.read_u32()?;
+        let ptr = r.read_u64()?;
+        Ok(FmrPushPull { len, ptr })
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct FmrDyld {
-    pub module: *mut c_char,
+    pub module: String,
+}
+
+impl FmrDyld {
+    pub fn encode<W: ProtoWrite + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        w.write_bytes(self.module.as_bytes())?;
+        w.write_u8(0)?;
+        Ok(())
+    }
+
+    pub fn decode<R: ProtoRead + ?Sized>(r: &mut R) -> io::Result<FmrDyld> {
+        let mut bytes = Vec::new();
+        loop {
+            let byte = r.read_u8()?;
+            if byte == 0 { break; }
+            bytes.push(byte);
+        }
+        let module = String::from_utf8(bytes).map_err(|_| invalid_data("module name wasn't valid UTF-8"))?;
+        Ok(FmrDyld { module })
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
-#[repr(C, packed)]
 pub struct FmrMemory {
     pub size: u32,
     pub ptr: u64,
 }
 
+impl FmrMemory {
+    pub fn encode<W: ProtoWrite + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        w.write_u32(self.size)?;
+        w.write_u64(self.ptr)?;
+        Ok(())
+    }
+
+    pub fn decode<R: ProtoRead + ?Sized>(r: &mut R) -> io::Result<FmrMemory> {
+        let size = r.read_u32()?;
+        let ptr = r.read_u64()?;
+        Ok(FmrMemory { size, ptr })
+    }
+}
+
+/// A device-reported error code carried in `FmrReturn.error`. `0` means
+/// success; every other byte is one of the codes below.
+pub const FMR_ERROR_UNKNOWN_MODULE: u8 = 1;
+pub const FMR_ERROR_NO_HANDLER: u8 = 2;
+pub const FMR_ERROR_MALLOC_FAILED: u8 = 3;
+pub const FMR_ERROR_INVALID_POINTER: u8 = 4;
+pub const FMR_ERROR_OVERFLOW: u8 = 5;
+
+/// A failure from one `Client` FMR round-trip: either the device reported
+/// an error byte, or something went wrong decoding its reply before the
+/// error byte could even be trusted.
+///
+/// This is its own type local to `runtime` rather than a variant folded
+/// into `crate::error::FlipperError`, the same way `protocol`'s other
+/// types stay local to the wire format they describe.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LfError {
+    /// `dyld`/`call` addressed a module index the device doesn't recognize.
+    UnknownModule,
+    /// `call` addressed a function the device has no handler for.
+    NoHandler,
+    /// `malloc` couldn't allocate memory of the requested size.
+    MallocFailed,
+    /// `free`/`push`/`pull` addressed memory the device doesn't recognize.
+    InvalidPointer,
+    /// The requested transfer doesn't fit in the device's address space.
+    Overflow,
+    /// A device error byte that isn't one of the named codes above.
+    Device(u8),
+    /// The reply's CRC didn't match its payload; the link may be corrupted.
+    CrcMismatch,
+    /// Reading or decoding the reply failed below the FMR error-byte layer.
+    Io(io::ErrorKind),
+}
+
+impl LfError {
+    /// Maps a nonzero `FmrReturn.error` byte to its named variant, falling
+    /// back to `Device` so the raw code is never lost.
+    pub fn from_code(code: u8) -> LfError {
+        match code {
+            FMR_ERROR_UNKNOWN_MODULE => LfError::UnknownModule,
+            FMR_ERROR_NO_HANDLER => LfError::NoHandler,
+            FMR_ERROR_MALLOC_FAILED => LfError::MallocFailed,
+            FMR_ERROR_INVALID_POINTER => LfError::InvalidPointer,
+            FMR_ERROR_OVERFLOW => LfError::Overflow,
+            code => LfError::Device(code),
+        }
+    }
+}
+
+impl From<io::Error> for LfError {
+    /// Lets `Client`'s default methods use `?` on the raw `Read`/`Write`
+    /// calls that send and receive an `FmrReturn`, alongside the `?` on
+    /// `decode_return`'s own `LfError` result.
+    fn from(err: io::Error) -> LfError {
+        LfError::Io(err.kind())
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
-#[repr(C, packed)]
 pub struct FmrReturn {
     pub value: LfValue,
     pub error: u8,
@@ -130,22 +444,62 @@ pub struct FmrReturn {
 impl FmrReturn {
     pub fn new() -> FmrReturn { FmrReturn { value: 0, error: 0 } }
 
-//    pub unsafe fn as_bytes(&self) -> &[u8] {
-//        slice::from_raw_parts(self as *const _ as *const u8, size_of::<FmrReturn>())
-//    }
+    /// Encodes this return value as `crc(2) value(8) error(1)`, the CRC
+    /// covering the `value` and `error` bytes the same way `FmrPacket::encode`
+    /// covers its own header and body.
+    pub fn encode<W: ProtoWrite + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        let mut body = Vec::with_capacity(9);
+        body.extend_from_slice(&self.value.to_le_bytes());
+        body.push(self.error);
 
-    pub unsafe fn as_bytes_mut(&mut self) -> &mut [u8] {
-        slice::from_raw_parts_mut(self as *mut _ as *mut u8, size_of::<FmrReturn>())
+        w.write_u16(calculate_crc(&body))?;
+        w.write_bytes(&body)?;
+        Ok(())
+    }
+
+    /// Decodes a return value and checks its CRC, so a corrupted reply is
+    /// reported as an error rather than trusted as-is.
+    pub fn decode<R: ProtoRead + ?Sized>(r: &mut R) -> io::Result<FmrReturn> {
+        let crc = r.read_u16()?;
+        let value = r.read_u64()?;
+        let error = r.read_u8()?;
+
+        let mut body = Vec::with_capacity(9);
+        body.extend_from_slice(&value.to_le_bytes());
+        body.push(error);
+        if calculate_crc(&body) != crc {
+            return Err(invalid_data("FmrReturn crc mismatch"));
+        }
+
+        Ok(FmrReturn { value, error })
     }
 }
 
 #[derive(Debug, Copy, Clone)]
-#[repr(C, packed)]
 pub struct LfArg {
     pub kind: LfType,
     pub value: LfArgRepr,
 }
 
+impl LfArg {
+    /// Encodes this argument as a fixed 9-byte record: a 1-byte type tag
+    /// followed by its full 8-byte value. `FmrCall::encode` packs arguments
+    /// more tightly, sizing each one from `kind.size()` against the call's
+    /// shared `argt` bitfield; this fixed-width form is for standalone
+    /// contexts that don't have such a bitfield to consult.
+    pub fn encode<W: ProtoWrite + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        w.write_u8(self.kind as u8)?;
+        w.write_u64(self.value)?;
+        Ok(())
+    }
+
+    pub fn decode<R: ProtoRead + ?Sized>(r: &mut R) -> io::Result<LfArg> {
+        let kind = LfType::from(r.read_u8()?).ok_or_else(|| invalid_data("unrecognized LfType"))?;
+        let value = r.read_u64()?;
+        Ok(LfArg { kind, value })
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 #[repr(u8)]
 pub enum LfType {
@@ -200,24 +554,181 @@ impl LfType {
     }
 }
 
-fn write_bytes<W: fmt::Write>(writer: &mut W, bytes: &[u8]) -> fmt::Result {
-    for chunk in bytes.chunks(8) {
-        for byte in chunk { write!(writer, "{:02X} ", byte)?; }
-        writeln!(writer)?;
+/// A single record drained from the device's ring-buffered log: a
+/// microsecond timestamp, a level byte (see
+/// `runtime::logging::level_from_byte` for the mapping to `log::Level`),
+/// and the message text. Framed as `timestamp_us(8) level(1) len(2)
+/// message(len)` so a run of records can be decoded back to back out of
+/// one pulled buffer without a record count up front.
+#[derive(Debug, Clone)]
+pub struct LogRecord {
+    pub timestamp_us: u64,
+    pub level: u8,
+    pub message: String,
+}
+
+impl LogRecord {
+    pub fn encode<W: ProtoWrite + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        w.write_u64(self.timestamp_us)?;
+        w.write_u8(self.level)?;
+        w.write_u16(self.message.len() as u16)?;
+        w.write_bytes(self.message.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn decode<R: ProtoRead + ?Sized>(r: &mut R) -> io::Result<LogRecord> {
+        let timestamp_us = r.read_u64()?;
+        let level = r.read_u8()?;
+        let len = r.read_u16()? as usize;
+        let mut bytes = vec![0u8; len];
+        r.read_bytes(&mut bytes)?;
+        let message = String::from_utf8(bytes).map_err(|_| invalid_data("log message wasn't valid UTF-8"))?;
+        Ok(LogRecord { timestamp_us, level, message })
     }
-    Ok(())
 }
 
-impl Debug for FmrBody {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let data = unsafe { & *(self as *const FmrBody as *const _ as *const u8) };
-        let bytes = unsafe { slice::from_raw_parts(data, size_of::<FmrBody>()) };
-        write_bytes(f, bytes)
+/// Computes a CRC-16/CCITT-FALSE checksum (polynomial `0x1021`, initial
+/// value `0xFFFF`, no input/output reflection, no final XOR) over `data`.
+pub fn calculate_crc(data: &[u8]) -> LfCrc {
+    let mut crc: u16 = 0xFFFF;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
     }
+    crc
 }
 
-impl Debug for FmrPayload {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write_bytes(f, &self.0)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_round_trip() {
+        let header = FmrHeader { magic: FMR_MAGIC_NUMBER, crc: 0xBEEF, len: 42, class: FmrClass::pull };
+
+        let mut bytes = Vec::new();
+        header.encode(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), FMR_HEADER_SIZE);
+
+        let decoded = FmrHeader::decode(&mut &bytes[..]).unwrap();
+        assert_eq!(decoded.magic, header.magic);
+        assert_eq!(decoded.crc, header.crc);
+        assert_eq!(decoded.len, header.len);
+        assert_eq!(decoded.class, header.class);
+    }
+
+    #[test]
+    fn test_call_packet_round_trip() {
+        let call = FmrCall {
+            module: 3,
+            function: 5,
+            ret: LfType::lf_uint32,
+            args: vec![
+                LfArg { kind: LfType::lf_uint8, value: 10 },
+                LfArg { kind: LfType::lf_uint16, value: 1000 },
+                LfArg { kind: LfType::lf_uint32, value: 2000 },
+                LfArg { kind: LfType::lf_uint64, value: 4000 },
+            ],
+        };
+
+        let mut packet = FmrPacket::new(FmrBody::Call(call));
+        let bytes = packet.encode().unwrap();
+        assert_eq!(bytes.len(), packet.header.len as usize);
+
+        let decoded = FmrPacket::decode(&mut &bytes[..]).unwrap();
+        assert_eq!(decoded.header.class, FmrClass::call);
+        match decoded.body {
+            FmrBody::Call(call) => {
+                assert_eq!(call.module, 3);
+                assert_eq!(call.function, 5);
+                assert_eq!(call.args.len(), 4);
+                assert_eq!(call.args[0].value, 10);
+                assert_eq!(call.args[1].value, 1000);
+                assert_eq!(call.args[2].value, 2000);
+                assert_eq!(call.args[3].value, 4000);
+            }
+            _ => panic!("expected a call body"),
+        }
+    }
+
+    #[test]
+    fn test_dyld_packet_round_trip() {
+        let mut packet = FmrPacket::new(FmrBody::Dyld(FmrDyld { module: "led".to_string() }));
+        let bytes = packet.encode().unwrap();
+
+        let decoded = FmrPacket::decode(&mut &bytes[..]).unwrap();
+        match decoded.body {
+            FmrBody::Dyld(dyld) => assert_eq!(dyld.module, "led"),
+            _ => panic!("expected a dyld body"),
+        }
+    }
+
+    #[test]
+    fn test_push_pull_packet_round_trip() {
+        let mut packet = FmrPacket::new(FmrBody::Push(FmrPushPull { len: 32, ptr: 0xDEAD_0000 }));
+        let bytes = packet.encode().unwrap();
+
+        let decoded = FmrPacket::decode(&mut &bytes[..]).unwrap();
+        assert_eq!(decoded.header.class, FmrClass::push);
+        match decoded.body {
+            FmrBody::Push(body) => {
+                assert_eq!(body.len, 32);
+                assert_eq!(body.ptr, 0xDEAD_0000);
+            }
+            _ => panic!("expected a push body"),
+        }
+    }
+
+    #[test]
+    fn test_malloc_free_packet_round_trip() {
+        let mut packet = FmrPacket::new(FmrBody::Free(FmrMemory { size: 0, ptr: 0xBEEF_0000 }));
+        let bytes = packet.encode().unwrap();
+
+        let decoded = FmrPacket::decode(&mut &bytes[..]).unwrap();
+        assert_eq!(decoded.header.class, FmrClass::free);
+        match decoded.body {
+            FmrBody::Free(body) => assert_eq!(body.ptr, 0xBEEF_0000),
+            _ => panic!("expected a free body"),
+        }
+    }
+
+    #[test]
+    fn test_encode_and_verify() {
+        let mut packet = FmrPacket::new(FmrBody::Malloc(FmrMemory { size: 64, ptr: 0 }));
+        packet.encode().unwrap();
+        assert!(packet.verify().unwrap());
+
+        packet.header.len += 1;
+        assert!(!packet.verify().unwrap());
+    }
+
+    #[test]
+    fn test_fmr_return_round_trip_and_crc_mismatch() {
+        let ret = FmrReturn { value: 0xDEAD_BEEF, error: FMR_ERROR_MALLOC_FAILED };
+        let mut bytes = Vec::new();
+        ret.encode(&mut bytes).unwrap();
+
+        let decoded = FmrReturn::decode(&mut &bytes[..]).unwrap();
+        assert_eq!(decoded.value, ret.value);
+        assert_eq!(decoded.error, ret.error);
+        assert_eq!(LfError::from_code(decoded.error), LfError::MallocFailed);
+
+        bytes[2] ^= 0xFF;
+        assert!(FmrReturn::decode(&mut &bytes[..]).is_err());
+    }
+
+    #[test]
+    fn test_log_record_round_trip() {
+        let record = LogRecord { timestamp_us: 123_456_789, level: 2, message: "module loaded".to_string() };
+
+        let mut bytes = Vec::new();
+        record.encode(&mut bytes).unwrap();
+
+        let decoded = LogRecord::decode(&mut &bytes[..]).unwrap();
+        assert_eq!(decoded.timestamp_us, record.timestamp_us);
+        assert_eq!(decoded.level, record.level);
+        assert_eq!(decoded.message, record.message);
     }
 }
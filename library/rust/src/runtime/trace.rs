@@ -0,0 +1,107 @@
+//! An optional, install-once tracing hook for the `Client` invoke/push/pull
+//! path, so latency and ordering issues (e.g. many rapid `Gpio::read`
+//! polls) can be diagnosed without instrumenting every call site by hand.
+//! Calls sent through `Batch` or `CallRecorder` are not traced.
+//!
+//! Tracing is a global, process-wide install rather than something threaded
+//! through every `Client` impl, since the devices that implement `Client`
+//! (`UsbDevice`, `Carbon`, `VirtualFlipper`, ...) have no spare field to
+//! carry a per-instance tracer through without breaking their `Read`/`Write`
+//! based construction.
+
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::protocol::{LfFunction, LfType};
+
+/// The FMR transaction a `Trace` describes.
+#[derive(Debug, Clone)]
+pub enum TraceEvent {
+    Invoke { module: String, function: LfFunction, argt: Vec<LfType>, ret: Option<u64> },
+    Push { pointer: u32, len: usize, ok: bool },
+    Pull { pointer: u32, len: usize, ok: bool },
+}
+
+/// A single recorded FMR transaction, timestamped when it completed.
+#[derive(Debug, Clone)]
+pub struct Trace {
+    /// Microseconds since the Unix epoch.
+    pub timestamp_us: u64,
+    pub event: TraceEvent,
+}
+
+type Tracer = Box<dyn Fn(Trace) + Send + Sync>;
+
+lazy_static! {
+    static ref TRACER: Mutex<Option<Tracer>> = Mutex::new(None);
+}
+
+/// Set whenever a tracer is installed, so the hot path can skip locking
+/// `TRACER` (let alone building a `Trace`) when nobody's listening.
+static TRACING_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Installs `tracer` to receive every `Trace` recorded from this point on,
+/// replacing whatever was installed before. Passing `None` removes it,
+/// returning the fast path to a single atomic load per call.
+pub fn set_tracer<F>(tracer: Option<F>)
+where
+    F: Fn(Trace) + Send + Sync + 'static,
+{
+    let boxed: Option<Tracer> = tracer.map(|f| Box::new(f) as Tracer);
+    TRACING_ENABLED.store(boxed.is_some(), Ordering::SeqCst);
+    *TRACER.lock().unwrap() = boxed;
+}
+
+fn now_us() -> u64 {
+    let elapsed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    elapsed.as_secs() * 1_000_000 + elapsed.subsec_micros() as u64
+}
+
+/// Hands `event` to the installed tracer, if any. Cheap/no-op when tracing
+/// hasn't been enabled: a single relaxed atomic load, no allocation, no
+/// lock.
+pub(crate) fn trace(event: TraceEvent) {
+    if !TRACING_ENABLED.load(Ordering::Relaxed) { return; }
+
+    if let Some(tracer) = TRACER.lock().unwrap().as_ref() {
+        tracer(Trace { timestamp_us: now_us(), event });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicUsize;
+
+    // `TRACER`/`TRACING_ENABLED` are process-global, so tests that install a
+    // tracer would otherwise race both each other and any other test in the
+    // crate that calls `trace()` (e.g. through `Client::invoke`/`push`/
+    // `pull`) under the default multi-threaded test runner. Hold this for
+    // the duration of any test that installs a tracer.
+    lazy_static! {
+        static ref TEST_GUARD: Mutex<()> = Mutex::new(());
+    }
+
+    #[test]
+    fn test_trace_is_noop_until_a_tracer_is_installed() {
+        let _guard = TEST_GUARD.lock().unwrap();
+        set_tracer::<fn(Trace)>(None);
+        trace(TraceEvent::Push { pointer: 0, len: 0, ok: true });
+        assert!(!TRACING_ENABLED.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_installed_tracer_receives_events() {
+        let _guard = TEST_GUARD.lock().unwrap();
+        let seen = Arc::new(AtomicUsize::new(0));
+        let seen_in_tracer = seen.clone();
+        set_tracer(Some(move |_: Trace| { seen_in_tracer.fetch_add(1, Ordering::SeqCst); }));
+
+        trace(TraceEvent::Pull { pointer: 0x1000, len: 4, ok: true });
+        assert_eq!(seen.load(Ordering::SeqCst), 1);
+
+        set_tracer::<fn(Trace)>(None);
+    }
+}